@@ -17,7 +17,7 @@ fn main() {
             .file("objc/notification.m")
             .flag("-fmodules")
             .flag("-Wno-deprecated-declarations")
-            .flag(&format!("-mmacos-version-min={}", min_version))
+            .flag(format!("-mmacos-version-min={}", min_version))
             .compile("notification");
 
         println!("cargo:rerun-if-env-changed={}", DEPLOYMENT_TARGET_VAR);
@@ -1,5 +1,7 @@
+#[cfg(target_os = "macos")]
 use mac_notifications::*;
 
+#[cfg(target_os = "macos")]
 fn main() {
     let mut provider = NotificationProvider::new("terminal");
 
@@ -30,3 +32,8 @@ fn main() {
         provider.run_main_loop_once();
     }
 }
+
+#[cfg(not(target_os = "macos"))]
+fn main() {
+    eprintln!("this example uses the NSUserNotification backend and only runs on macOS");
+}
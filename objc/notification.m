@@ -24,33 +24,27 @@ NSString *fakeBundleIdentifier = nil;
 
 BOOL installNSBundleHook()
 {
+    static BOOL installed = NO;
+    if (installed) {
+        return YES;
+    }
     Class class = objc_getClass("NSBundle");
     if (class) {
         method_exchangeImplementations(class_getInstanceMethod(class, @selector(bundleIdentifier)),
                                        class_getInstanceMethod(class, @selector(__bundleIdentifier)));
+        installed = YES;
         return YES;
     }
 	return NO;
 }
 
-
-
-void init(NSString* appName) {
-    @autoreleasepool {
-        NSString* findString = [NSString stringWithFormat:@"get id of application \"%@\"", appName];
-        NSAppleScript* findScript = [[NSAppleScript alloc] initWithSource:findString];
-        NSAppleEventDescriptor* resultDescriptor = [findScript executeAndReturnError:nil];
-
-        NSString *newbundleIdentifier = [NSString stringWithFormat:@"%@", [resultDescriptor stringValue]];
-        [findScript release];
-
-        if (installNSBundleHook()) {
-            NSArray<NSURL*> *urls = CFBridgingRelease(LSCopyApplicationURLsForBundleIdentifier((CFStringRef)newbundleIdentifier, NULL));
-            if (urls.count > 0) {
-                [fakeBundleIdentifier release]; // Release old value - nil is ok
-                fakeBundleIdentifier = newbundleIdentifier;
-                [newbundleIdentifier retain]; // Retain new value - it outlives this scope
-            }
-        }
+// Makes the main bundle report the given bundle identifier
+BOOL set_bundle_identifier(NSString* bundleIdentifier) {
+    if (!installNSBundleHook()) {
+        return NO;
     }
+    NSString *newBundleIdentifier = [bundleIdentifier copy]; // Retained - it outlives this scope
+    [fakeBundleIdentifier release]; // Release old value - nil is ok
+    fakeBundleIdentifier = newBundleIdentifier;
+    return YES;
 }
@@ -0,0 +1,372 @@
+//! # Alertmanager
+//! Shows the alerts of a [Prometheus Alertmanager](https://prometheus.io/docs/alerting/latest/configuration/#webhook_config)
+//! as notifications. Point a webhook receiver of Alertmanager at an `AlertmanagerReceiver`:
+//! ```yaml
+//! receivers:
+//!   - name: desktop
+//!     webhook_configs:
+//!       - url: http://127.0.0.1:9095/alerts
+//! ```
+//! Every alert group gets one notification, its identifier is derived from the `groupKey`.
+//! A new message of the group replaces the notification and once the group is resolved
+//! the notification is replaced or deleted, see `Resolved`.
+
+use crate::server::{read_body, Failure, Handler, JsonServer};
+use crate::{Notification, NotificationError, NotificationHandle};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread::JoinHandle;
+use tiny_http::{Method, Request};
+
+/// Requests with a bigger body are rejected
+const MAX_BODY: usize = 1024 * 1024;
+
+/// Whether an alert or a whole group is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Firing,
+    Resolved,
+}
+
+/// A single alert of a `Webhook`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    pub status: Status,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    /// RFC 3339 timestamp
+    #[serde(default)]
+    pub starts_at: String,
+    /// RFC 3339 timestamp, `0001-01-01T00:00:00Z` while the alert is firing
+    #[serde(default)]
+    pub ends_at: String,
+    #[serde(default, rename = "generatorURL")]
+    pub generator_url: String,
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+impl Alert {
+    /// The `description` or `summary` annotation, the labels if there is neither
+    pub fn text(&self) -> String {
+        match ["description", "summary"]
+            .iter()
+            .find_map(|name| self.annotations.get(*name))
+        {
+            Some(text) => text.clone(),
+            None => self
+                .labels
+                .iter()
+                .filter(|(name, _)| *name != "alertname")
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+}
+
+/// The payload Alertmanager posts to a webhook receiver (version 4)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    #[serde(default)]
+    pub version: String,
+    /// Identifies the alert group
+    pub group_key: String,
+    pub status: Status,
+    #[serde(default)]
+    pub receiver: String,
+    #[serde(default)]
+    pub group_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub common_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub common_annotations: BTreeMap<String, String>,
+    #[serde(default, rename = "externalURL")]
+    pub external_url: String,
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+    /// Number of alerts which did not fit into the payload
+    #[serde(default)]
+    pub truncated_alerts: usize,
+}
+
+impl Webhook {
+    pub fn from_json(json: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(json)
+    }
+
+    /// The identifier of the notification of the alert group
+    pub fn identifier(&self) -> String {
+        format!("alertmanager:{}", self.group_key)
+    }
+
+    /// The `alertname` of the group, the name of the receiver if the group has none
+    pub fn alert_name(&self) -> &str {
+        self.group_labels
+            .get("alertname")
+            .or_else(|| self.common_labels.get("alertname"))
+            .unwrap_or(&self.receiver)
+    }
+
+    /// The alerts with the given status
+    pub fn alerts(&self, status: Status) -> impl Iterator<Item = &Alert> {
+        self.alerts
+            .iter()
+            .filter(move |alert| alert.status == status)
+    }
+
+    /// Renders the group like the default templates of Alertmanager, e.g. `[FIRING:2] HighLatency`.
+    /// The `summary` annotation becomes the subtitle and the texts of the alerts the body.
+    /// Firing alerts play the default sound
+    pub fn to_notification(&self) -> Notification {
+        let firing = self.alerts(Status::Firing).count() + self.truncated_alerts;
+        let title = match self.status {
+            Status::Firing => format!("[FIRING:{}] {}", firing, self.alert_name()),
+            Status::Resolved => format!("[RESOLVED] {}", self.alert_name()),
+        };
+        let alerts = match self.status {
+            Status::Firing => self.alerts(Status::Firing).collect::<Vec<_>>(),
+            Status::Resolved => self.alerts.iter().collect(),
+        };
+        let mut texts: Vec<String> = alerts.iter().map(|alert| alert.text()).collect();
+        texts.dedup();
+        let mut notification = Notification::new()
+            .identifier(&self.identifier())
+            .title(&title)
+            .body(&texts.join("\n"));
+        if let Some(summary) = self.common_annotations.get("summary") {
+            notification = notification.subtitle(summary);
+        }
+        if self.status == Status::Firing {
+            notification = notification.sound("default");
+        }
+        notification
+    }
+}
+
+/// What happens to the notification of a group once it is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Resolved {
+    /// A `[RESOLVED]` notification replaces the firing one
+    #[default]
+    Replace,
+    /// The notification is deleted
+    Delete,
+}
+
+/// Shows the webhook through the handle, the notification of the group is replaced or deleted.
+/// Returns the identifier of the notification, `None` if it was deleted
+pub fn apply(
+    handle: &NotificationHandle,
+    webhook: &Webhook,
+    resolved: Resolved,
+) -> Result<Option<String>, NotificationError> {
+    // the firing notification of the group is always removed, not every backend replaces it by itself
+    handle.delete(&webhook.identifier()).wait()?;
+    if webhook.status == Status::Resolved && resolved == Resolved::Delete {
+        return Ok(None);
+    }
+    handle.send(webhook.to_notification()).wait()?.map(Some)
+}
+
+/// Receives the webhooks of Alertmanager on a local port
+/// # Example
+/// ```rust
+/// use mac_notifications::alertmanager::AlertmanagerReceiver;
+/// use mac_notifications::backend::MockBackend;
+/// use mac_notifications::NotificationProvider;
+/// use std::io::{Read, Write};
+/// use std::net::{SocketAddr, TcpStream};
+/// use std::thread;
+///
+/// fn post(address: SocketAddr, body: &str) -> String {
+///     let mut stream = TcpStream::connect(address).unwrap();
+///     write!(
+///         stream,
+///         "POST /alerts HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+///          Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+///         body.len(),
+///         body
+///     )
+///     .unwrap();
+///     let mut response = String::new();
+///     stream.read_to_string(&mut response).unwrap();
+///     response
+/// }
+///
+/// // recorded from Alertmanager
+/// let firing = r#"{
+///   "version": "4",
+///   "groupKey": "{}:{alertname=\"DiskFull\"}",
+///   "status": "firing",
+///   "receiver": "desktop",
+///   "groupLabels": { "alertname": "DiskFull" },
+///   "commonLabels": { "alertname": "DiskFull", "severity": "critical" },
+///   "commonAnnotations": { "summary": "Disk almost full" },
+///   "externalURL": "http://alertmanager:9093",
+///   "alerts": [
+///     {
+///       "status": "firing",
+///       "labels": { "alertname": "DiskFull", "instance": "db-1" },
+///       "annotations": { "description": "/var on db-1 is 97% full" },
+///       "startsAt": "2024-05-01T10:00:00Z",
+///       "endsAt": "0001-01-01T00:00:00Z",
+///       "generatorURL": "http://prometheus:9090/graph",
+///       "fingerprint": "a1b2c3"
+///     }
+///   ],
+///   "truncatedAlerts": 0
+/// }"#;
+/// let resolved = firing.replace(r#""status": "firing""#, r#""status": "resolved""#);
+///
+/// let mock = MockBackend::new();
+/// let provider = NotificationProvider::with_backend(mock.clone());
+/// let receiver = AlertmanagerReceiver::bind("127.0.0.1:0", provider.handle()).unwrap();
+/// let address = receiver.local_addr().unwrap();
+/// receiver.spawn();
+///
+/// let client = thread::spawn(move || (post(address, firing), post(address, &resolved)));
+/// while !client.is_finished() {
+///     provider.run_main_loop_once();
+/// }
+/// let (first, second) = client.join().unwrap();
+/// assert!(first.starts_with("HTTP/1.1 200") && second.starts_with("HTTP/1.1 200"));
+///
+/// let sent = mock.sent();
+/// assert_eq!(sent[0].get_title(), Some("[FIRING:1] DiskFull"));
+/// assert_eq!(sent[0].get_body(), Some("/var on db-1 is 97% full"));
+/// // the resolved notification replaced the firing one
+/// let delivered = provider.get_all_notifications();
+/// assert_eq!(delivered.len(), 1);
+/// assert_eq!(delivered[0].get_title(), Some("[RESOLVED] DiskFull"));
+/// assert_eq!(delivered[0].get_identifier(), sent[0].get_identifier());
+/// ```
+pub struct AlertmanagerReceiver {
+    server: JsonServer<Receiver>,
+}
+
+impl AlertmanagerReceiver {
+    /// Listens on the address of the webhook URL configured in Alertmanager, e.g. "127.0.0.1:9095"
+    pub fn bind<A: ToSocketAddrs>(address: A, handle: NotificationHandle) -> io::Result<Self> {
+        let receiver = Receiver {
+            handle,
+            resolved: Resolved::default(),
+        };
+        Ok(Self {
+            server: JsonServer::bind(address, receiver)?,
+        })
+    }
+
+    /// What happens to the notification of a resolved group
+    pub fn resolved(mut self, resolved: Resolved) -> Self {
+        self.server.handler.resolved = resolved;
+        self
+    }
+
+    /// The address for the webhook URL, with the actual port if the receiver was bound to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.local_addr()
+    }
+
+    /// Receives webhooks until the server fails. Alertmanager gets its answer
+    /// once the notification of the group was sent, replaced or deleted
+    pub fn run(&self) -> io::Result<()> {
+        self.server.run()
+    }
+
+    /// Runs `run` on a new thread, the webhooks wait for the provider to process its commands
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        self.server.spawn()
+    }
+}
+
+/// Turns the webhooks of an `AlertmanagerReceiver` into notifications
+struct Receiver {
+    handle: NotificationHandle,
+    resolved: Resolved,
+}
+
+impl Handler for Receiver {
+    fn respond(&self, request: &mut Request) -> (u16, serde_json::Value) {
+        match self.receive(request) {
+            Ok(identifier) => (200, serde_json::json!({ "status": "ok", "id": identifier })),
+            Err((status, error)) => (
+                status,
+                serde_json::json!({ "status": "error", "error": error }),
+            ),
+        }
+    }
+}
+
+impl Receiver {
+    fn receive(&self, request: &mut Request) -> Result<Option<String>, Failure> {
+        if *request.method() != Method::Post {
+            return Err((405, "method not allowed".to_string()));
+        }
+        let body = read_body(request, MAX_BODY)?;
+        let webhook =
+            Webhook::from_json(&body).map_err(|err| (400, format!("invalid webhook: {}", err)))?;
+        apply(&self.handle, &webhook, self.resolved).map_err(|err| (500, err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::NotificationProvider;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    const FIRING: &str = r#"{"groupKey":"{}:{alertname=\"DiskFull\"}","status":"firing"}"#;
+
+    /// Sends the request to a receiver and returns the status code and the number of sent notifications
+    fn status(method: &str, body: Vec<u8>) -> (u16, usize) {
+        let mock = MockBackend::new();
+        let provider = NotificationProvider::with_backend(mock.clone());
+        let receiver = AlertmanagerReceiver::bind("127.0.0.1:0", provider.handle()).unwrap();
+        let address = receiver.local_addr().unwrap();
+        receiver.spawn();
+        let method = method.to_string();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let head = format!(
+                "{} /alerts HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+                method,
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response);
+            response[9..12].parse().unwrap()
+        });
+        while !client.is_finished() {
+            provider.process_commands();
+        }
+        (client.join().unwrap(), mock.sent().len())
+    }
+
+    #[test]
+    fn oversized_webhooks_are_rejected() {
+        let mut body = FIRING.as_bytes().to_vec();
+        assert_eq!(status("POST", body.clone()), (200, 1));
+        // still valid JSON, only too big
+        body.resize(MAX_BODY + 1, b' ');
+        assert_eq!(status("POST", body), (413, 0));
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected() {
+        assert_eq!(status("GET", vec![]), (405, 0));
+        assert_eq!(status("POST", b"{}".to_vec()), (400, 0));
+    }
+}
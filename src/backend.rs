@@ -0,0 +1,53 @@
+//! # Backends
+//! A backend is the part of the crate that actually talks to the notification system of the OS.
+//! `Notification::send` and `NotificationProvider` only ever go through the `NotificationBackend` trait,
+//! therefore the notification logic of an application can be exercised with any implementation of it.
+//!
+//! The backend of the most recently created `NotificationProvider` on the current thread is used by `Notification::send`.
+
+use crate::notification::NotificationError;
+use crate::{Notification, NotificationEvent};
+
+#[cfg(feature = "dbus")]
+mod dbus;
+#[cfg(target_os = "macos")]
+mod macos;
+mod mock;
+
+#[cfg(feature = "dbus")]
+pub use dbus::DbusBackend;
+#[cfg(target_os = "macos")]
+pub use macos::MacOSBackend;
+pub use mock::MockBackend;
+
+/// Callback that receives the events of all notifications
+pub type EventCallback = dyn Fn(NotificationEvent);
+
+/// Abstraction over the notification system of the OS
+pub trait NotificationBackend {
+    /// Shows the notification immediately
+    fn deliver(&self, notification: &Notification) -> Result<(), NotificationError>;
+    /// Hands the notification over to the OS which shows it at its delivery date
+    fn schedule(&self, notification: &Notification) -> Result<(), NotificationError>;
+    /// Returns all notifications which are currently shown
+    fn delivered_notifications(&self) -> Vec<Notification>;
+    /// Returns all notifications which are waiting for their delivery date
+    fn scheduled_notifications(&self) -> Vec<Notification>;
+    /// Removes a delivered notification by its identifier
+    fn remove(&self, identifier: &str);
+    /// Removes all delivered notifications
+    fn remove_all(&self);
+    /// Removes a scheduled notification before it is delivered
+    fn remove_scheduled(&self, identifier: &str);
+    /// Removes all scheduled notifications
+    fn remove_all_scheduled(&self);
+    /// Registers the callback for deliveries and user interactions. A previously registered callback gets replaced
+    fn subscribe(&self, callback: Box<EventCallback>);
+    /// Gives the backend the chance to process pending events, e.g. by running the main loop for a short time
+    fn run_main_loop_once(&self) {}
+    /// Whether events only arrive while `run_main_loop_once` runs. Backends which report their events
+    /// right away, like `MockBackend`, keep the default
+    fn has_main_loop(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,390 @@
+use crate::backend::{EventCallback, NotificationBackend};
+use crate::notification::NotificationError;
+use crate::{Notification, NotificationEvent, NotificationResponse};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use zbus::blocking::{Connection, MessageIterator, Proxy};
+use zbus::message::Type;
+use zbus::zvariant::Value;
+use zbus::MatchRule;
+
+const SERVICE: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+
+/// Action key the server reports when the notification itself was clicked
+const DEFAULT_ACTION: &str = "default";
+/// Action key which turns on the inline reply field on servers with the `inline-reply` capability
+const REPLY_ACTION: &str = "inline-reply";
+/// Action key of the custom close button, the protocol itself has no such button
+const CLOSE_ACTION: &str = "mac-notifications-close";
+/// Reason of `NotificationClosed` if the notification expired
+const CLOSED_EXPIRED: u32 = 1;
+/// Reason of `NotificationClosed` if the user dismissed the notification
+const CLOSED_BY_USER: u32 = 2;
+
+impl From<zbus::Error> for NotificationError {
+    fn from(value: zbus::Error) -> Self {
+        Self::Backend(value.to_string())
+    }
+}
+
+enum Signal {
+    ActionInvoked(u32, String),
+    NotificationClosed(u32, u32),
+    NotificationReplied(u32, String),
+}
+
+#[derive(Default)]
+struct State {
+    /// identifier of the notification -> id assigned by the notification server
+    ids: HashMap<String, u32>,
+    delivered: Vec<Notification>,
+    scheduled: Vec<Notification>,
+    /// server ids of notifications the user already interacted with
+    activated: HashSet<u32>,
+    /// server id -> title of the custom close button
+    close_buttons: HashMap<u32, String>,
+    callback: Option<Rc<EventCallback>>,
+}
+
+impl State {
+    fn identifier(&self, server_id: u32) -> Option<String> {
+        self.ids
+            .iter()
+            .find(|(_, id)| **id == server_id)
+            .map(|(identifier, _)| identifier.clone())
+    }
+}
+
+/// Backend for Linux and BSD desktops implementing the [Desktop Notifications Specification](https://specifications.freedesktop.org/notification-spec/latest/)
+///
+/// The title of a notification becomes the summary. Subtitle and body are joined by a line break to form the body.
+/// The freedesktop protocol has no scheduling, notifications with a delivery date are kept by the backend
+/// and shown by `run_main_loop_once` as soon as they are due. If the notification server fails to show one,
+/// it stays scheduled and is tried again by the next `run_main_loop_once`.
+/// A custom close button is added as an additional action. Closing the notification with the close button of the
+/// notification server is reported as `NotificationResponse::Dismissed`, expiring as `NotificationResponse::None`.
+/// # Example
+/// ```rust no_run
+/// use mac_notifications::backend::DbusBackend;
+/// use mac_notifications::{Notification, NotificationProvider};
+///
+/// let backend = DbusBackend::new("My App").unwrap();
+/// let mut provider = NotificationProvider::with_backend(backend);
+/// provider.set_callback(|id, resp| {
+///     println!("Notification {} clicked: {:?}", id, resp);
+/// });
+///
+/// Notification::new().title("Hello").reply(true).send().unwrap();
+/// for _ in 0..50 {
+///     provider.run_main_loop_once();
+/// }
+/// ```
+pub struct DbusBackend {
+    proxy: Proxy<'static>,
+    app_name: String,
+    capabilities: Vec<String>,
+    signals: Receiver<Signal>,
+    state: RefCell<State>,
+}
+
+impl DbusBackend {
+    /// Connects to the notification server on the session bus
+    pub fn new(app_name: &str) -> Result<Self, NotificationError> {
+        Self::with_connection(Connection::session()?, app_name)
+    }
+
+    /// Uses an existing connection, e.g. to a private bus
+    pub fn with_connection(
+        connection: Connection,
+        app_name: &str,
+    ) -> Result<Self, NotificationError> {
+        let proxy = Proxy::new(&connection, SERVICE, PATH, INTERFACE)?;
+        let capabilities: Vec<String> = proxy.call("GetCapabilities", &())?;
+
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface(INTERFACE)?
+            .path(PATH)?
+            .build();
+        let messages = MessageIterator::for_match_rule(rule, &connection, None)?;
+        let (sender, signals) = mpsc::channel();
+        thread::spawn(move || {
+            for message in messages.flatten() {
+                let header = message.header();
+                let body = message.body();
+                let signal = match header.member().map(|member| member.as_str()) {
+                    Some("ActionInvoked") => body
+                        .deserialize::<(u32, String)>()
+                        .ok()
+                        .map(|(id, key)| Signal::ActionInvoked(id, key)),
+                    Some("NotificationClosed") => body
+                        .deserialize::<(u32, u32)>()
+                        .ok()
+                        .map(|(id, reason)| Signal::NotificationClosed(id, reason)),
+                    Some("NotificationReplied") => body
+                        .deserialize::<(u32, String)>()
+                        .ok()
+                        .map(|(id, text)| Signal::NotificationReplied(id, text)),
+                    _ => None,
+                };
+                if let Some(signal) = signal {
+                    if sender.send(signal).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            proxy,
+            app_name: app_name.to_string(),
+            capabilities,
+            signals,
+            state: RefCell::new(State::default()),
+        })
+    }
+
+    /// Capabilities reported by the notification server, e.g. `actions` or `inline-reply`
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    fn notify(&self, notification: &Notification) -> Result<u32, NotificationError> {
+        let replaces_id = self
+            .state
+            .borrow()
+            .ids
+            .get(notification.get_identifier())
+            .copied()
+            .unwrap_or(0);
+
+        let mut actions = vec![];
+        if self.has_capability("actions") {
+            actions.extend([DEFAULT_ACTION, ""]);
+            for action in notification
+                .get_action()
+                .into_iter()
+                .chain(notification.get_alternate_actions())
+            {
+                actions.extend([action.identifier.as_str(), action.title.as_str()]);
+            }
+            if let Some(close_button) = notification.get_close_button() {
+                actions.extend([CLOSE_ACTION, close_button]);
+            }
+        }
+        if notification.get_reply() && self.has_capability(REPLY_ACTION) {
+            actions.extend([REPLY_ACTION, "Reply"]);
+        }
+
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        if let Some(image) = notification.get_image() {
+            hints.insert("image-path", Value::from(image));
+        }
+        if let Some(sound) = notification.get_sound() {
+            if sound.starts_with('/') {
+                hints.insert("sound-file", Value::from(sound));
+            } else {
+                hints.insert("sound-name", Value::from(sound));
+            }
+        }
+
+        let body = match (notification.get_subtitle(), notification.get_body()) {
+            (Some(subtitle), Some(body)) => format!("{}\n{}", subtitle, body),
+            (subtitle, body) => subtitle.or(body).unwrap_or_default().to_string(),
+        };
+
+        let id: u32 = self.proxy.call(
+            "Notify",
+            &(
+                self.app_name.as_str(),
+                replaces_id,
+                "",
+                notification.get_title().unwrap_or_default(),
+                body.as_str(),
+                actions,
+                hints,
+                -1i32,
+            ),
+        )?;
+        Ok(id)
+    }
+
+    fn deliver_due(&self) {
+        let now = SystemTime::now();
+        let due: Vec<Notification> = {
+            let mut state = self.state.borrow_mut();
+            let (due, pending): (Vec<Notification>, Vec<Notification>) =
+                std::mem::take(&mut state.scheduled)
+                    .into_iter()
+                    .partition(|n| n.get_delivery_date().is_none_or(|date| date <= now));
+            state.scheduled = pending;
+            due
+        };
+        for notification in due {
+            // recurring notifications are scheduled again for their next repetition,
+            // a failed delivery stays scheduled and is tried again with the next run of the main loop
+            let next = match self.deliver(&notification) {
+                Ok(()) => notification.next_occurrence(now),
+                Err(_) => Some(notification),
+            };
+            self.state.borrow_mut().scheduled.extend(next);
+        }
+    }
+
+    fn handle(&self, signal: Signal) {
+        let response = {
+            let mut state = self.state.borrow_mut();
+            match signal {
+                Signal::ActionInvoked(id, key) => {
+                    let identifier = state.identifier(id);
+                    state.activated.insert(id);
+                    match key.as_str() {
+                        // the text arrives with NotificationReplied
+                        REPLY_ACTION => None,
+                        DEFAULT_ACTION => identifier.map(|i| (i, NotificationResponse::Click)),
+                        CLOSE_ACTION => {
+                            let title = state.close_buttons.get(&id).cloned().unwrap_or_default();
+                            identifier.map(|i| (i, NotificationResponse::CloseButton(title)))
+                        }
+                        _ => identifier.map(|i| (i, NotificationResponse::ActionButton(key))),
+                    }
+                }
+                Signal::NotificationReplied(id, text) => {
+                    state.activated.insert(id);
+                    state
+                        .identifier(id)
+                        .map(|i| (i, NotificationResponse::Reply(text)))
+                }
+                Signal::NotificationClosed(id, reason) => {
+                    let identifier = state.identifier(id);
+                    let activated = state.activated.remove(&id);
+                    state.close_buttons.remove(&id);
+                    if let Some(identifier) = identifier.as_ref() {
+                        state.ids.remove(identifier);
+                        state.delivered.retain(|n| n.get_identifier() != identifier);
+                    }
+                    match reason {
+                        _ if activated => None,
+                        CLOSED_EXPIRED => identifier.map(|i| (i, NotificationResponse::None)),
+                        CLOSED_BY_USER => identifier.map(|i| (i, NotificationResponse::Dismissed)),
+                        _ => None,
+                    }
+                }
+            }
+        };
+
+        if let Some((identifier, response)) = response {
+            self.emit(NotificationEvent::from_response(identifier, response));
+        }
+    }
+
+    fn emit(&self, event: NotificationEvent) {
+        let callback = self.state.borrow().callback.clone();
+        if let Some(callback) = callback {
+            callback(event);
+        }
+    }
+}
+
+impl NotificationBackend for DbusBackend {
+    fn deliver(&self, notification: &Notification) -> Result<(), NotificationError> {
+        let id = self.notify(notification)?;
+        let identifier = notification.get_identifier();
+        {
+            let mut state = self.state.borrow_mut();
+            state.delivered.retain(|n| n.get_identifier() != identifier);
+            state.delivered.push(notification.clone());
+            state.ids.insert(identifier.to_string(), id);
+            if let Some(close_button) = notification.get_close_button() {
+                state.close_buttons.insert(id, close_button.to_string());
+            }
+        }
+        self.emit(NotificationEvent::Delivered {
+            identifier: identifier.to_string(),
+        });
+        Ok(())
+    }
+
+    fn schedule(&self, notification: &Notification) -> Result<(), NotificationError> {
+        let notification = notification
+            .clone()
+            .delivery_date(notification.first_delivery());
+        self.state.borrow_mut().scheduled.push(notification);
+        self.deliver_due();
+        Ok(())
+    }
+
+    fn delivered_notifications(&self) -> Vec<Notification> {
+        self.state.borrow().delivered.clone()
+    }
+
+    fn scheduled_notifications(&self) -> Vec<Notification> {
+        self.state.borrow().scheduled.clone()
+    }
+
+    fn remove(&self, identifier: &str) {
+        let id = {
+            let mut state = self.state.borrow_mut();
+            state.delivered.retain(|n| n.get_identifier() != identifier);
+            let id = state.ids.remove(identifier);
+            if let Some(id) = id {
+                state.close_buttons.remove(&id);
+            }
+            id
+        };
+        if let Some(id) = id {
+            let _: Result<(), _> = self.proxy.call("CloseNotification", &(id,));
+        }
+    }
+
+    fn remove_all(&self) {
+        let ids: Vec<u32> = {
+            let mut state = self.state.borrow_mut();
+            state.delivered.clear();
+            state.close_buttons.clear();
+            state.ids.drain().map(|(_, id)| id).collect()
+        };
+        for id in ids {
+            let _: Result<(), _> = self.proxy.call("CloseNotification", &(id,));
+        }
+    }
+
+    fn remove_scheduled(&self, identifier: &str) {
+        let mut state = self.state.borrow_mut();
+        state.scheduled.retain(|n| n.get_identifier() != identifier);
+    }
+
+    fn remove_all_scheduled(&self) {
+        self.state.borrow_mut().scheduled.clear();
+    }
+
+    fn subscribe(&self, callback: Box<EventCallback>) {
+        self.state.borrow_mut().callback = Some(Rc::from(callback));
+    }
+
+    /// Delivers due scheduled notifications and waits up to .1 seconds for signals of the notification server
+    fn run_main_loop_once(&self) {
+        self.deliver_due();
+        match self.signals.recv_timeout(Duration::from_millis(100)) {
+            Ok(signal) => self.handle(signal),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return,
+        }
+        while let Ok(signal) = self.signals.try_recv() {
+            self.handle(signal);
+        }
+    }
+
+    fn has_main_loop(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,283 @@
+use crate::backend::{EventCallback, NotificationBackend};
+use crate::delegate::RustNotificationDelegate;
+use crate::notification::{notification_identifier, NotificationError};
+use crate::provider::run_main_loop_once;
+use crate::{Notification, NotificationEvent, NotificationResponse};
+use objc2::rc::Id;
+use objc2::runtime::NSObjectProtocol;
+use objc2::ClassType;
+use objc2_foundation::{MainThreadMarker, NSArray, NSUserNotification, NSUserNotificationCenter};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+#[derive(Default)]
+struct State {
+    callback: Option<Rc<EventCallback>>,
+    /// identifiers of all notifications sent by this backend -> title of their close button
+    close_buttons: HashMap<String, Option<String>>,
+    /// identifiers of our notifications which were shown during the last run of the main loop
+    visible: HashSet<String>,
+    /// identifiers of notifications the delegate reported an interaction for
+    activated: HashSet<String>,
+}
+
+/// Backend using the [NSUserNotificationCenter](https://developer.apple.com/documentation/foundation/nsusernotificationcenter) of macOS
+///
+/// NSUserNotificationCenter does not report dismissals. Instead `run_main_loop_once` compares the
+/// delivered notifications with the previous run: notifications which vanished without being activated
+/// are reported as `NotificationResponse::CloseButton` if they had a custom close button and as
+/// `NotificationResponse::Dismissed` otherwise. macOS does not tell whether the close button was used or the notification was swiped away.
+pub struct MacOSBackend {
+    center: Id<NSUserNotificationCenter>,
+    delegate: RefCell<Option<Id<RustNotificationDelegate>>>,
+    state: Rc<RefCell<State>>,
+}
+
+impl MacOSBackend {
+    /// Creates a backend for the default user notification center
+    /// # Panics
+    /// Panics if the function is not called on the main thread
+    pub fn new() -> Self {
+        MainThreadMarker::new().expect("MacOSBackend::new() must be on the main thread");
+        let center = unsafe { NSUserNotificationCenter::defaultUserNotificationCenter() };
+        Self {
+            center,
+            delegate: RefCell::new(None),
+            state: Rc::new(RefCell::new(State::default())),
+        }
+    }
+
+    fn to_notifications(notifications_array: Id<NSArray<NSUserNotification>>) -> Vec<Notification> {
+        let mut notifications: Vec<Notification> = vec![];
+        let count = notifications_array.count();
+        for i in 0..count {
+            let notification = unsafe { notifications_array.objectAtIndex(i) };
+            let notification = Notification::from(notification.as_ref());
+            // weekly notifications consist of one NSUserNotification per weekday
+            match notifications
+                .iter_mut()
+                .find(|n| n.get_identifier() == notification.get_identifier())
+            {
+                Some(existing) => {
+                    if notification.get_delivery_date() < existing.get_delivery_date() {
+                        *existing = notification;
+                    }
+                }
+                None => notifications.push(notification),
+            }
+        }
+        notifications
+    }
+
+    /// All NSUserNotifications of the center with the given identifier
+    fn find(
+        notifications_array: &NSArray<NSUserNotification>,
+        identifier: &str,
+    ) -> Vec<Id<NSUserNotification>> {
+        (0..notifications_array.count())
+            .map(|i| unsafe { notifications_array.objectAtIndex(i) })
+            .filter(|n| notification_identifier(n).as_deref() == Some(identifier))
+            .collect()
+    }
+
+    fn track(&self, notification: &Notification) {
+        self.state.borrow_mut().close_buttons.insert(
+            notification.get_identifier().to_string(),
+            notification.get_close_button().map(|s| s.to_string()),
+        );
+    }
+
+    /// Reports notifications which disappeared from the notification center without an interaction
+    fn report_dismissals(&self) {
+        let delivered: HashSet<String> = unsafe {
+            let notifications_array = self.center.deliveredNotifications();
+            (0..notifications_array.count())
+                .filter_map(|i| notification_identifier(&notifications_array.objectAtIndex(i)))
+                .collect()
+        };
+
+        let dismissed = {
+            let mut state = self.state.borrow_mut();
+            let vanished: Vec<String> = state.visible.difference(&delivered).cloned().collect();
+            let mut dismissed = vec![];
+            for identifier in vanished {
+                let close_button = state.close_buttons.remove(&identifier).flatten();
+                if state.activated.remove(&identifier) {
+                    continue;
+                }
+                let response = match close_button {
+                    Some(title) => NotificationResponse::CloseButton(title),
+                    None => NotificationResponse::Dismissed,
+                };
+                dismissed.push((identifier, response));
+            }
+            let State {
+                visible,
+                close_buttons,
+                ..
+            } = &mut *state;
+            *visible = delivered
+                .into_iter()
+                .filter(|id| close_buttons.contains_key(id))
+                .collect();
+            dismissed
+        };
+
+        let callback = self.state.borrow().callback.clone();
+        if let Some(callback) = callback {
+            for (identifier, response) in dismissed {
+                callback(NotificationEvent::from_response(identifier, response));
+            }
+        }
+    }
+}
+
+impl Default for MacOSBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NotificationBackend for MacOSBackend {
+    fn deliver(&self, notification: &Notification) -> Result<(), NotificationError> {
+        MainThreadMarker::new().expect("send() must be on the main thread");
+        let ns_notifications = notification.to_ns_notifications()?;
+        self.track(notification);
+        for ns_notification in ns_notifications {
+            unsafe {
+                self.center.deliverNotification(ns_notification.as_ref());
+            }
+        }
+        Ok(())
+    }
+
+    fn schedule(&self, notification: &Notification) -> Result<(), NotificationError> {
+        MainThreadMarker::new().expect("send() must be on the main thread");
+        let ns_notifications = notification.to_ns_notifications()?;
+        self.track(notification);
+        for ns_notification in ns_notifications {
+            unsafe {
+                self.center.scheduleNotification(ns_notification.as_ref());
+            }
+        }
+        Ok(())
+    }
+
+    fn delivered_notifications(&self) -> Vec<Notification> {
+        MainThreadMarker::new().expect("get_all_notifications() must be on the main thread");
+        Self::to_notifications(unsafe { self.center.deliveredNotifications() })
+    }
+
+    fn scheduled_notifications(&self) -> Vec<Notification> {
+        MainThreadMarker::new().expect("get_scheduled_notifications() must be on the main thread");
+        Self::to_notifications(unsafe { self.center.scheduledNotifications() })
+    }
+
+    fn remove(&self, identifier: &str) {
+        MainThreadMarker::new().expect("delete() must be on the main thread");
+        {
+            let mut state = self.state.borrow_mut();
+            state.visible.remove(identifier);
+            state.close_buttons.remove(identifier);
+        }
+        unsafe {
+            let notifications_array = self.center.deliveredNotifications();
+            for notification in Self::find(&notifications_array, identifier) {
+                self.center.removeDeliveredNotification(&notification);
+            }
+        }
+    }
+
+    fn remove_all(&self) {
+        MainThreadMarker::new().expect("delete_all() must be on the main thread");
+        {
+            let mut state = self.state.borrow_mut();
+            let State {
+                visible,
+                close_buttons,
+                ..
+            } = &mut *state;
+            for identifier in visible.drain() {
+                close_buttons.remove(&identifier);
+            }
+        }
+        unsafe {
+            self.center.removeAllDeliveredNotifications();
+        }
+    }
+
+    fn remove_scheduled(&self, identifier: &str) {
+        MainThreadMarker::new().expect("cancel_scheduled() must be on the main thread");
+        self.state.borrow_mut().close_buttons.remove(identifier);
+        unsafe {
+            let notifications_array = self.center.scheduledNotifications();
+            for notification in Self::find(&notifications_array, identifier) {
+                self.center.removeScheduledNotification(&notification);
+            }
+        }
+    }
+
+    fn remove_all_scheduled(&self) {
+        MainThreadMarker::new().expect("cancel_all_scheduled() must be on the main thread");
+        unsafe {
+            let notifications_array = self.center.scheduledNotifications();
+            {
+                let mut state = self.state.borrow_mut();
+                for i in 0..notifications_array.count() {
+                    if let Some(id) = notification_identifier(&notifications_array.objectAtIndex(i))
+                    {
+                        state.close_buttons.remove(&id);
+                    }
+                }
+            }
+            self.center
+                .setScheduledNotifications(&NSArray::from_vec(vec![]));
+        }
+    }
+
+    fn subscribe(&self, callback: Box<EventCallback>) {
+        self.state.borrow_mut().callback = Some(Rc::from(callback));
+        if self.delegate.borrow().is_some() {
+            return;
+        }
+        let state = self.state.clone();
+        let delegate = RustNotificationDelegate::new(move |event| {
+            if !matches!(event, NotificationEvent::Delivered { .. }) {
+                state
+                    .borrow_mut()
+                    .activated
+                    .insert(event.identifier().to_string());
+            }
+            let callback = state.borrow().callback.clone();
+            if let Some(callback) = callback {
+                callback(event);
+            }
+        });
+        *self.delegate.borrow_mut() = Some(delegate);
+    }
+
+    fn run_main_loop_once(&self) {
+        run_main_loop_once();
+        self.report_dismissals();
+    }
+
+    fn has_main_loop(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for MacOSBackend {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(delegate) = self.center.delegate() {
+                if delegate
+                    .as_ref()
+                    .isKindOfClass(RustNotificationDelegate::class())
+                {
+                    self.center.setDelegate(None);
+                }
+            }
+        }
+    }
+}
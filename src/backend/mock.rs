@@ -0,0 +1,310 @@
+use crate::backend::{EventCallback, NotificationBackend};
+use crate::notification::NotificationError;
+use crate::{Notification, NotificationEvent, NotificationResponse};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+#[derive(Default)]
+struct State {
+    sent: Vec<Notification>,
+    delivered: Vec<Notification>,
+    scheduled: Vec<Notification>,
+    callback: Option<Rc<EventCallback>>,
+}
+
+impl State {
+    /// Like the notification systems of the OS, a notification replaces a delivered one with the same identifier
+    fn show(&mut self, notification: &Notification) {
+        let identifier = notification.get_identifier();
+        self.delivered.retain(|n| n.get_identifier() != identifier);
+        self.delivered.push(notification.clone());
+    }
+}
+
+/// In-memory backend for tests
+///
+/// Records every notification it gets, keeps a fake list of delivered and scheduled notifications
+/// and lets the test simulate user interactions. Clones share the same state, so one clone can be handed
+/// to the `NotificationProvider` while the test keeps the other one.
+/// # Example
+/// ```rust
+/// use mac_notifications::backend::MockBackend;
+/// use mac_notifications::{Notification, NotificationProvider, NotificationResponse};
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// let mock = MockBackend::new();
+/// let mut provider = NotificationProvider::with_backend(mock.clone());
+///
+/// let responses = Rc::new(RefCell::new(vec![]));
+/// let recorded = responses.clone();
+/// provider.set_callback(move |id, resp| recorded.borrow_mut().push((id, resp)));
+///
+/// let id = Notification::new().title("Hello").body("World").reply(true).send().unwrap();
+/// assert_eq!(mock.sent()[0].get_body(), Some("World"));
+/// assert_eq!(provider.get_all_notifications().len(), 1);
+///
+/// // user replied "ok"
+/// mock.reply(&id, "ok");
+/// assert_eq!(
+///     responses.borrow().as_slice(),
+///     &[(id.clone(), NotificationResponse::Reply("ok".to_string()))]
+/// );
+///
+/// // user swiped the notification away
+/// mock.dismiss(&id);
+/// assert!(provider.get_all_notifications().is_empty());
+/// ```
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Rc<RefCell<State>>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns every notification that was delivered or scheduled through this backend, in order
+    pub fn sent(&self) -> Vec<Notification> {
+        self.state.borrow().sent.clone()
+    }
+
+    /// Moves all scheduled notifications to the delivered ones, as if their delivery date was reached.
+    /// Recurring notifications stay scheduled for their next repetition
+    pub fn deliver_scheduled(&self) {
+        let scheduled = {
+            let mut state = self.state.borrow_mut();
+            let scheduled = std::mem::take(&mut state.scheduled);
+            for notification in scheduled.iter() {
+                state.show(notification);
+            }
+            state.scheduled = scheduled
+                .iter()
+                .filter_map(|n| n.next_occurrence(SystemTime::now()))
+                .collect();
+            scheduled
+        };
+        for notification in scheduled {
+            self.emit(NotificationEvent::Delivered {
+                identifier: notification.get_identifier().to_string(),
+            });
+        }
+    }
+
+    /// Simulates an interaction of the user with the notification.
+    /// The response is passed to the callback registered with `NotificationProvider::set_callback`
+    pub fn activate(&self, identifier: &str, response: NotificationResponse) {
+        self.emit(NotificationEvent::from_response(
+            identifier.to_string(),
+            response,
+        ));
+    }
+
+    fn emit(&self, event: NotificationEvent) {
+        // the callback may call back into the backend, therefore the state must not be borrowed
+        let callback = self.state.borrow().callback.clone();
+        if let Some(callback) = callback {
+            callback(event);
+        }
+    }
+
+    /// Simulates a click on the notification
+    pub fn click(&self, identifier: &str) {
+        self.activate(identifier, NotificationResponse::Click);
+    }
+
+    /// Simulates a reply with the given text
+    pub fn reply(&self, identifier: &str, text: &str) {
+        self.activate(identifier, NotificationResponse::Reply(text.to_string()));
+    }
+
+    /// Simulates a click on the action with the given identifier
+    pub fn press_action(&self, identifier: &str, action: &str) {
+        self.activate(
+            identifier,
+            NotificationResponse::ActionButton(action.to_string()),
+        );
+    }
+
+    /// Simulates a click on the close button. The notification is removed from the delivered ones
+    pub fn close(&self, identifier: &str) {
+        let title = self
+            .state
+            .borrow()
+            .delivered
+            .iter()
+            .find(|n| n.get_identifier() == identifier)
+            .and_then(|n| n.get_close_button().map(|s| s.to_string()))
+            .unwrap_or_else(|| "Close".to_string());
+        self.remove(identifier);
+        self.activate(identifier, NotificationResponse::CloseButton(title));
+    }
+
+    /// Simulates the user swiping the notification away. The notification is removed from the delivered ones
+    pub fn dismiss(&self, identifier: &str) {
+        self.remove(identifier);
+        self.activate(identifier, NotificationResponse::Dismissed);
+    }
+
+    /// Simulates the notification timing out without any interaction. The notification is removed from the delivered ones
+    pub fn expire(&self, identifier: &str) {
+        self.remove(identifier);
+        self.activate(identifier, NotificationResponse::None);
+    }
+}
+
+impl NotificationBackend for MockBackend {
+    fn deliver(&self, notification: &Notification) -> Result<(), NotificationError> {
+        {
+            let mut state = self.state.borrow_mut();
+            state.sent.push(notification.clone());
+            state.show(notification);
+        }
+        self.emit(NotificationEvent::Delivered {
+            identifier: notification.get_identifier().to_string(),
+        });
+        Ok(())
+    }
+
+    fn schedule(&self, notification: &Notification) -> Result<(), NotificationError> {
+        let mut state = self.state.borrow_mut();
+        state.sent.push(notification.clone());
+        state.scheduled.push(
+            notification
+                .clone()
+                .delivery_date(notification.first_delivery()),
+        );
+        Ok(())
+    }
+
+    fn delivered_notifications(&self) -> Vec<Notification> {
+        self.state.borrow().delivered.clone()
+    }
+
+    fn scheduled_notifications(&self) -> Vec<Notification> {
+        self.state.borrow().scheduled.clone()
+    }
+
+    fn remove(&self, identifier: &str) {
+        let mut state = self.state.borrow_mut();
+        state.delivered.retain(|n| n.get_identifier() != identifier);
+    }
+
+    fn remove_all(&self) {
+        self.state.borrow_mut().delivered.clear();
+    }
+
+    fn remove_scheduled(&self, identifier: &str) {
+        let mut state = self.state.borrow_mut();
+        state.scheduled.retain(|n| n.get_identifier() != identifier);
+    }
+
+    fn remove_all_scheduled(&self) {
+        self.state.borrow_mut().scheduled.clear();
+    }
+
+    fn subscribe(&self, callback: Box<EventCallback>) {
+        self.state.borrow_mut().callback = Some(Rc::from(callback));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Recurrence;
+    use std::time::Duration;
+
+    fn ids(notifications: Vec<Notification>) -> Vec<String> {
+        notifications
+            .iter()
+            .map(|n| n.get_identifier().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn delivering_an_identifier_again_replaces_the_notification() {
+        let mock = MockBackend::new();
+        let first = Notification::new().identifier("build").title("10%");
+        let second = Notification::new().identifier("build").title("90%");
+        mock.deliver(&first).unwrap();
+        mock.deliver(&Notification::new().identifier("deploy"))
+            .unwrap();
+        mock.deliver(&second).unwrap();
+
+        assert_eq!(ids(mock.delivered_notifications()), vec!["deploy", "build"]);
+        assert_eq!(mock.delivered_notifications()[1].get_title(), Some("90%"));
+        // every delivery is recorded
+        assert_eq!(mock.sent().len(), 3);
+    }
+
+    #[test]
+    fn recurring_notifications_are_delivered_once() {
+        let mock = MockBackend::new();
+        let start = SystemTime::now() + Duration::from_secs(60);
+        let daily = Notification::new()
+            .identifier("daily")
+            .delivery_date(start)
+            .recurrence(Recurrence::Days(1));
+        mock.schedule(&daily).unwrap();
+        mock.deliver_scheduled();
+        mock.deliver_scheduled();
+
+        assert_eq!(ids(mock.delivered_notifications()), vec!["daily"]);
+        assert_eq!(ids(mock.scheduled_notifications()), vec!["daily"]);
+        let next = mock.scheduled_notifications()[0]
+            .get_delivery_date()
+            .unwrap();
+        assert!(next > start);
+    }
+
+    #[test]
+    fn events_reach_the_callback() {
+        let mock = MockBackend::new();
+        let events = Rc::new(RefCell::new(vec![]));
+        let recorded = events.clone();
+        mock.subscribe(Box::new(move |event| recorded.borrow_mut().push(event)));
+
+        let notification = Notification::new()
+            .identifier("backup")
+            .close_button("Ignore");
+        mock.deliver(&notification).unwrap();
+        mock.close("backup");
+        mock.deliver(&notification).unwrap();
+        mock.expire("backup");
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                NotificationEvent::Delivered {
+                    identifier: "backup".to_string()
+                },
+                NotificationEvent::from_response(
+                    "backup".to_string(),
+                    NotificationResponse::CloseButton("Ignore".to_string())
+                ),
+                NotificationEvent::Delivered {
+                    identifier: "backup".to_string()
+                },
+                NotificationEvent::from_response("backup".to_string(), NotificationResponse::None),
+            ]
+        );
+        assert!(mock.delivered_notifications().is_empty());
+    }
+
+    #[test]
+    fn removing_scheduled_notifications_keeps_the_delivered_ones() {
+        let mock = MockBackend::new();
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        mock.deliver(&Notification::new().identifier("now"))
+            .unwrap();
+        mock.schedule(&Notification::new().identifier("later").delivery_date(later))
+            .unwrap();
+        mock.remove_all_scheduled();
+        assert_eq!(ids(mock.delivered_notifications()), vec!["now"]);
+        assert!(mock.scheduled_notifications().is_empty());
+        mock.remove("now");
+        assert!(mock.delivered_notifications().is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+//! # mac-notify
+//! Sends a notification from the command line, e.g. at the end of a build script:
+//! ```bash
+//! $ mac-notify --title "Build finished" --body "all tests passed" --sound default
+//! $ mac-notify --title "Deploy?" --action deploy=Deploy --close Cancel --wait
+//! {"identifier":"…","response":{"action_button":"deploy"}}
+//! ```
+//! Without `--wait` the identifier of the notification is printed.
+
+use chrono::DateTime;
+use clap::Parser;
+use mac_notifications::{
+    Notification, NotificationAction, NotificationError, NotificationProvider, NotificationResponse,
+};
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Parser, Debug)]
+#[command(name = "mac-notify", version, about = "Sends a macOS notification")]
+struct Args {
+    /// The title of the notification
+    #[arg(short, long)]
+    title: Option<String>,
+    /// The subtitle of the notification
+    #[arg(short, long)]
+    subtitle: Option<String>,
+    /// The body text of the notification
+    #[arg(short, long, alias = "message")]
+    body: Option<String>,
+    /// The name of the sound, "default" for the default sound
+    #[arg(long)]
+    sound: Option<String>,
+    /// File path or URL of an image shown next to the text
+    #[arg(long)]
+    image: Option<String>,
+    /// Shows a text field to reply to the notification
+    #[arg(short, long)]
+    reply: bool,
+    /// Action button as IDENTIFIER=TITLE, the first one is the main button, the others end up in its drop down menu
+    #[arg(short, long = "action", value_name = "IDENTIFIER=TITLE", value_parser = parse_action)]
+    actions: Vec<NotificationAction>,
+    /// Custom title of the close button
+    #[arg(long, value_name = "TITLE")]
+    close: Option<String>,
+    /// Delivers the notification at the given time, e.g. 2030-01-01T09:00:00+01:00
+    #[arg(long, value_name = "RFC3339", value_parser = parse_date, conflicts_with = "delay")]
+    at: Option<SystemTime>,
+    /// Delivers the notification after the given number of seconds
+    #[arg(long, value_name = "SECONDS")]
+    delay: Option<u64>,
+    /// Sends the notification as the application with this name, e.g. "Terminal"
+    #[arg(long)]
+    app: Option<String>,
+    /// Sends the notification as the application with this bundle identifier, e.g. "com.apple.Terminal"
+    #[arg(long)]
+    bundle_id: Option<String>,
+    /// Fails instead of sending as the Finder if the application can not be found
+    #[arg(long)]
+    no_fallback: bool,
+    /// Waits for the user and prints the response as JSON
+    #[arg(short, long)]
+    wait: bool,
+    /// Gives up waiting after the given number of seconds
+    #[arg(long, value_name = "SECONDS", requires = "wait")]
+    timeout: Option<u64>,
+}
+
+fn parse_action(value: &str) -> Result<NotificationAction, String> {
+    match value.split_once('=') {
+        Some(("", _)) => Err("the identifier of the action is empty".to_string()),
+        Some((identifier, title)) => Ok(NotificationAction::new(identifier, title)),
+        None => Ok(NotificationAction::new(value, value)),
+    }
+}
+
+fn parse_date(value: &str) -> Result<SystemTime, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(SystemTime::from)
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn provider(args: &Args) -> Result<NotificationProvider, NotificationError> {
+    let mut builder = NotificationProvider::builder().fallback(!args.no_fallback);
+    if let Some(bundle_id) = args.bundle_id.as_deref() {
+        builder = builder.bundle_identifier(bundle_id);
+    }
+    if let Some(app) = args.app.as_deref() {
+        builder = builder.app_name(app);
+    }
+    builder.build()
+}
+
+#[cfg(all(not(target_os = "macos"), feature = "dbus"))]
+fn provider(args: &Args) -> Result<NotificationProvider, NotificationError> {
+    use mac_notifications::backend::DbusBackend;
+
+    let app = args.app.as_deref().unwrap_or("mac-notify");
+    Ok(NotificationProvider::with_backend(DbusBackend::new(app)?))
+}
+
+#[cfg(all(not(target_os = "macos"), not(feature = "dbus")))]
+fn provider(_args: &Args) -> Result<NotificationProvider, NotificationError> {
+    Err(NotificationError::NotSupported)
+}
+
+fn notification(args: &Args) -> Notification {
+    let mut notification = Notification::new().reply(args.reply);
+    if let Some(title) = args.title.as_deref() {
+        notification = notification.title(title);
+    }
+    if let Some(subtitle) = args.subtitle.as_deref() {
+        notification = notification.subtitle(subtitle);
+    }
+    if let Some(body) = args.body.as_deref() {
+        notification = notification.body(body);
+    }
+    if let Some(sound) = args.sound.as_deref() {
+        notification = notification.sound(sound);
+    }
+    if let Some(image) = args.image.as_deref() {
+        notification = notification.image(image);
+    }
+    if let Some((action, alternate_actions)) = args.actions.split_first() {
+        notification = notification.action(&action.identifier, &action.title);
+        for action in alternate_actions {
+            notification = notification.alternate_action(&action.identifier, &action.title);
+        }
+    }
+    if let Some(close) = args.close.as_deref() {
+        notification = notification.close_button(close);
+    }
+    let delivery_date = args
+        .at
+        .or_else(|| Some(SystemTime::now() + Duration::from_secs(args.delay?)));
+    if let Some(delivery_date) = delivery_date {
+        notification = notification.delivery_date(delivery_date);
+    }
+    notification
+}
+
+fn run(args: &Args) -> Result<(), NotificationError> {
+    let provider = provider(args)?;
+    let configured = args.app.is_some() || args.bundle_id.is_some();
+    if let Some(resolution) = provider
+        .bundle_resolution()
+        .filter(|r| configured && r.is_fallback())
+    {
+        eprintln!(
+            "application not found, sending as {}",
+            resolution.identifier()
+        );
+    }
+    if !args.wait {
+        println!("{}", notification(args).send()?);
+        return Ok(());
+    }
+
+    let (sender, responses) = mpsc::channel();
+    let identifier = notification(args)
+        .on_response(move |response| {
+            let _ = sender.send(response);
+        })
+        .send()?;
+    let deadline = args
+        .timeout
+        .map(|timeout| Instant::now() + Duration::from_secs(timeout));
+    let response: NotificationResponse = loop {
+        provider.run_main_loop_once();
+        if let Ok(response) = responses.try_recv() {
+            break response;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            provider.delete(&identifier);
+            return Err(NotificationError::Timeout);
+        }
+    };
+    let output = serde_json::json!({ "identifier": identifier, "response": response });
+    println!("{}", output);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("mac-notify: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! # mac-notifyd
+//! Owns a `NotificationProvider` and serves it on a Unix domain socket, see the `daemon` module for the protocol:
+//! ```bash
+//! $ mac-notifyd --app Terminal &
+//! $ echo '{"command":"send","notification":{"title":"Hello"}}' | nc -U "${XDG_RUNTIME_DIR:-$TMPDIR}/mac-notifyd.sock"
+//! {"type":"sent","identifier":"…"}
+//! ```
+
+use clap::Parser;
+use mac_notifications::daemon::Daemon;
+use mac_notifications::{NotificationError, NotificationProvider};
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "mac-notifyd",
+    version,
+    about = "Sends macOS notifications for other processes"
+)]
+struct Args {
+    /// Path of the socket, defaults to mac-notifyd.sock in $XDG_RUNTIME_DIR or the temporary directory
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    /// Sends the notifications as the application with this name, e.g. "Terminal"
+    #[arg(long)]
+    app: Option<String>,
+    /// Sends the notifications as the application with this bundle identifier, e.g. "com.apple.Terminal"
+    #[arg(long)]
+    bundle_id: Option<String>,
+    /// Fails instead of sending as the Finder if the application can not be found
+    #[arg(long)]
+    no_fallback: bool,
+}
+
+#[cfg(target_os = "macos")]
+fn provider(args: &Args) -> Result<NotificationProvider, NotificationError> {
+    let mut builder = NotificationProvider::builder().fallback(!args.no_fallback);
+    if let Some(bundle_id) = args.bundle_id.as_deref() {
+        builder = builder.bundle_identifier(bundle_id);
+    }
+    if let Some(app) = args.app.as_deref() {
+        builder = builder.app_name(app);
+    }
+    builder.build()
+}
+
+#[cfg(all(not(target_os = "macos"), feature = "dbus"))]
+fn provider(args: &Args) -> Result<NotificationProvider, NotificationError> {
+    use mac_notifications::backend::DbusBackend;
+
+    let app = args.app.as_deref().unwrap_or("mac-notifyd");
+    Ok(NotificationProvider::with_backend(DbusBackend::new(app)?))
+}
+
+#[cfg(all(not(target_os = "macos"), not(feature = "dbus")))]
+fn provider(_args: &Args) -> Result<NotificationProvider, NotificationError> {
+    Err(NotificationError::NotSupported)
+}
+
+/// The temporary directory is private on macOS, other systems keep private runtime files in $XDG_RUNTIME_DIR
+fn runtime_dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let provider = match provider(&args) {
+        Ok(provider) => provider,
+        Err(err) => {
+            eprintln!("mac-notifyd: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let socket = args
+        .socket
+        .clone()
+        .unwrap_or_else(|| runtime_dir().join("mac-notifyd.sock"));
+    let daemon = match Daemon::bind(&socket, provider.handle()) {
+        Ok(daemon) => daemon,
+        Err(err) => {
+            eprintln!(
+                "mac-notifyd: could not listen on {}: {}",
+                socket.display(),
+                err
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!("listening on {}", daemon.path().display());
+    let listener = daemon.spawn();
+
+    // the provider and its main loop have to stay on the main thread
+    while !listener.is_finished() {
+        provider.run_main_loop_once();
+    }
+    if let Ok(Err(err)) = listener.join() {
+        eprintln!("mac-notifyd: {}", err);
+    }
+    ExitCode::FAILURE
+}
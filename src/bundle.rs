@@ -0,0 +1,424 @@
+//! # Bundle identifiers
+//! macOS only shows notifications of applications with a bundle identifier.
+//! Command line tools have none, so the provider makes the main bundle report the identifier of another application.
+//! `BundleResolution` tells which identifier was picked and why.
+//!
+//! Installed applications are found by `AppBundleResolver`, which reads the `Info.plist` of the bundles
+//! in the application directories and their vendor folders, e.g. "/Applications/Microsoft Office".
+//! It works without macOS, e.g. on fixture bundles in tests.
+
+use crate::NotificationError;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the bundle identifier used by a `NotificationProvider` comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleSource {
+    /// Set with `NotificationProviderBuilder::bundle_identifier`
+    Explicit,
+    /// Looked up by the name of an application, see `NotificationProviderBuilder::app_name`
+    AppName,
+    /// The application could not be resolved, `FALLBACK_BUNDLE_IDENTIFIER` is used
+    Fallback,
+}
+
+/// How many levels of folders inside an application directory are searched for bundles
+const MAX_DEPTH: usize = 2;
+
+/// The identifier of the Finder, used if nothing else could be resolved
+pub const FALLBACK_BUNDLE_IDENTIFIER: &str = "com.apple.finder";
+
+/// The bundle identifier a `NotificationProvider` sends its notifications with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleResolution {
+    identifier: String,
+    source: BundleSource,
+}
+
+impl BundleResolution {
+    pub(crate) fn new(identifier: &str, source: BundleSource) -> Self {
+        Self {
+            identifier: identifier.to_string(),
+            source,
+        }
+    }
+
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    pub fn source(&self) -> BundleSource {
+        self.source
+    }
+
+    /// True if the configured application could not be resolved
+    pub fn is_fallback(&self) -> bool {
+        self.source == BundleSource::Fallback
+    }
+}
+
+/// An application bundle found by `AppBundleResolver`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppBundle {
+    /// The name of the bundle without `.app`
+    pub name: String,
+    /// `CFBundleIdentifier` of the `Info.plist`
+    pub identifier: String,
+    /// The path of the `.app` directory
+    pub path: PathBuf,
+    /// `CFBundleName` and `CFBundleDisplayName` of the `Info.plist`
+    pub display_names: Vec<String>,
+}
+
+impl AppBundle {
+    /// Reads `Contents/Info.plist` of the bundle, XML and binary property lists are supported.
+    /// `None` if it is missing, malformed or has no `CFBundleIdentifier`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let info = plist::Value::from_file(path.join("Contents").join("Info.plist")).ok()?;
+        let info = info.as_dictionary()?;
+        let string = |key: &str| info.get(key).and_then(|value| value.as_string());
+        Some(Self {
+            name: path.file_stem()?.to_string_lossy().to_string(),
+            identifier: string("CFBundleIdentifier")?.to_string(),
+            path: path.to_path_buf(),
+            display_names: ["CFBundleName", "CFBundleDisplayName"]
+                .into_iter()
+                .filter_map(string)
+                .map(|name| name.to_string())
+                .collect(),
+        })
+    }
+
+    /// Whether the application is called `name`, ignoring the case like the Finder does
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .display_names
+                .iter()
+                .any(|display_name| display_name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Finds installed applications by scanning application directories for `.app` bundles
+/// # Example
+/// ```rust
+/// use mac_notifications::AppBundleResolver;
+///
+/// if let Some(terminal) = AppBundleResolver::new().find("Terminal") {
+///     println!("{} is installed at {}", terminal.identifier, terminal.path.display());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppBundleResolver {
+    directories: Vec<PathBuf>,
+}
+
+impl Default for AppBundleResolver {
+    /// Scans the application directories of macOS and `~/Applications`
+    fn default() -> Self {
+        let mut directories: Vec<PathBuf> = [
+            "/Applications",
+            "/Applications/Utilities",
+            "/System/Applications",
+            "/System/Applications/Utilities",
+            "/System/Library/CoreServices",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+        if let Some(home) = env::var_os("HOME") {
+            directories.push(Path::new(&home).join("Applications"));
+        }
+        Self { directories }
+    }
+}
+
+impl AppBundleResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only scans the given directories
+    pub fn with_directories<I, P>(directories: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Self {
+            directories: directories
+                .into_iter()
+                .map(|directory| directory.as_ref().to_path_buf())
+                .collect(),
+        }
+    }
+
+    /// Adds a directory which is scanned after the others
+    pub fn directory<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.directories.push(directory.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn directories(&self) -> &[PathBuf] {
+        &self.directories
+    }
+
+    /// All bundles in the directories and the folders inside them, missing directories and broken bundles are skipped
+    pub fn bundles(&self) -> impl Iterator<Item = AppBundle> + '_ {
+        // "/Applications/Utilities" is a folder of "/Applications" as well
+        let mut seen = HashSet::new();
+        self.directories
+            .iter()
+            .flat_map(|directory| app_paths(directory, MAX_DEPTH))
+            .filter(move |path| seen.insert(path.clone()))
+            .filter_map(|path| AppBundle::from_path(&path))
+    }
+
+    /// Finds an application by its name, e.g. "Terminal"
+    pub fn find(&self, name: &str) -> Option<AppBundle> {
+        self.bundles().find(|bundle| bundle.is_named(name))
+    }
+
+    /// Finds an application by its bundle identifier, e.g. "com.apple.Terminal"
+    pub fn find_by_identifier(&self, identifier: &str) -> Option<AppBundle> {
+        self.bundles()
+            .find(|bundle| bundle.identifier.eq_ignore_ascii_case(identifier))
+    }
+
+    /// Picks the bundle identifier the way `NotificationProviderBuilder` does: an explicit identifier has to be installed,
+    /// otherwise the identifier of the named application and finally `FALLBACK_BUNDLE_IDENTIFIER` if `fallback` is set.
+    /// Fails with `NotificationError::AppNotFound` if the explicit identifier is not installed, regardless of `fallback`
+    pub fn resolve(
+        &self,
+        bundle_identifier: Option<&str>,
+        app_name: Option<&str>,
+        fallback: bool,
+    ) -> Result<BundleResolution, NotificationError> {
+        if let Some(identifier) = bundle_identifier {
+            return match self.find_by_identifier(identifier) {
+                Some(bundle) => Ok(BundleResolution::new(
+                    &bundle.identifier,
+                    BundleSource::Explicit,
+                )),
+                None => Err(NotificationError::AppNotFound(identifier.to_string())),
+            };
+        }
+        if let Some(bundle) = app_name.and_then(|name| self.find(name)) {
+            return Ok(BundleResolution::new(
+                &bundle.identifier,
+                BundleSource::AppName,
+            ));
+        }
+        match (fallback, app_name) {
+            (true, _) => Ok(BundleResolution::new(
+                FALLBACK_BUNDLE_IDENTIFIER,
+                BundleSource::Fallback,
+            )),
+            (false, Some(app_name)) => Err(NotificationError::AppNotFound(app_name.to_string())),
+            (false, None) => Err(NotificationError::BundleNotResolved),
+        }
+    }
+}
+
+/// The `.app` bundles in the directory sorted by path, followed by the ones in its folders up to `depth` levels deep
+fn app_paths(directory: &Path, depth: usize) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    paths.sort();
+    let (mut bundles, folders): (Vec<PathBuf>, Vec<PathBuf>) = paths
+        .into_iter()
+        .partition(|path| path.extension().is_some_and(|extension| extension == "app"));
+    if depth > 0 {
+        for folder in folders {
+            bundles.extend(app_paths(&folder, depth - 1));
+        }
+    }
+    bundles
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) mod sys {
+    use objc2_foundation::NSString;
+    use std::ops::Deref;
+
+    mod ffi {
+        use objc2::runtime::Bool;
+        use objc2_foundation::NSString;
+
+        #[link(name = "notification")]
+        extern "C" {
+            pub fn set_bundle_identifier(bundle_identifier: *const NSString) -> Bool;
+        }
+    }
+
+    /// Makes the main bundle report the given identifier, false if the hook could not be installed
+    pub fn set_bundle_identifier(bundle_identifier: &str) -> bool {
+        let bundle_identifier = NSString::from_str(bundle_identifier);
+        unsafe { ffi::set_bundle_identifier(bundle_identifier.deref()).as_bool() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of applications which is removed again
+    struct Applications(PathBuf);
+
+    impl Applications {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!(
+                "mac-notifications-bundles-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        /// Creates the `Contents` folder of a bundle at the relative path
+        fn contents(&self, bundle: &str) -> PathBuf {
+            let contents = self.0.join(bundle).join("Contents");
+            fs::create_dir_all(&contents).unwrap();
+            contents
+        }
+
+        fn write(&self, bundle: &str, info: &str) {
+            fs::write(self.contents(bundle).join("Info.plist"), info).unwrap();
+        }
+
+        fn write_binary(&self, bundle: &str, identifier: &str) {
+            let mut info = plist::Dictionary::new();
+            info.insert("CFBundleIdentifier".into(), identifier.into());
+            plist::Value::Dictionary(info)
+                .to_file_binary(self.contents(bundle).join("Info.plist"))
+                .unwrap();
+        }
+
+        fn resolver(&self) -> AppBundleResolver {
+            AppBundleResolver::with_directories([&self.0])
+        }
+    }
+
+    impl Drop for Applications {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const EXAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleIdentifier</key>
+    <string>com.example.app</string>
+    <key>CFBundleDisplayName</key>
+    <string>Example App</string>
+</dict>
+</plist>"#;
+
+    #[test]
+    fn reads_xml_property_lists() {
+        let applications = Applications::new("xml");
+        applications.write("Example.app", EXAMPLE);
+        let resolver = applications.resolver();
+
+        let bundle = resolver.find("example").unwrap();
+        assert_eq!(bundle.identifier, "com.example.app");
+        assert_eq!(bundle.path, applications.0.join("Example.app"));
+        assert_eq!(resolver.find("Example App").unwrap().name, "Example");
+        assert!(resolver.find_by_identifier("COM.EXAMPLE.APP").is_some());
+        assert!(resolver.find("Missing").is_none());
+    }
+
+    #[test]
+    fn reads_binary_property_lists() {
+        let applications = Applications::new("binary");
+        applications.write_binary("Binary.app", "com.example.binary");
+        let bundle = applications.resolver().find("binary").unwrap();
+        assert_eq!(bundle.identifier, "com.example.binary");
+        assert!(bundle.display_names.is_empty());
+    }
+
+    #[test]
+    fn finds_bundles_in_vendor_folders() {
+        let applications = Applications::new("vendor");
+        applications.write_binary("Example Suite/Nested.app", "com.example.nested");
+        applications.write_binary("a/b/c/Deep.app", "com.example.deep");
+        let resolver = applications.resolver();
+
+        let bundle = resolver.find_by_identifier("com.example.nested").unwrap();
+        assert_eq!(bundle.name, "Nested");
+        assert!(resolver.find("Deep").is_none());
+    }
+
+    #[test]
+    fn skips_broken_bundles() {
+        let applications = Applications::new("broken");
+        applications.contents("Missing.app");
+        applications.write("Malformed.app", "<plist><dict><key>");
+        applications.write(
+            "Anonymous.app",
+            "<plist version=\"1.0\"><dict><key>CFBundleName</key><string>Anonymous</string></dict></plist>",
+        );
+        applications.write("Example.app", EXAMPLE);
+
+        let names: Vec<String> = applications
+            .resolver()
+            .bundles()
+            .map(|bundle| bundle.name)
+            .collect();
+        assert_eq!(names, ["Example"]);
+        assert!(AppBundle::from_path(&applications.0.join("Malformed.app")).is_none());
+    }
+
+    #[test]
+    fn resolves_like_the_provider() {
+        let applications = Applications::new("resolve");
+        applications.write("Example.app", EXAMPLE);
+        let resolver = applications.resolver();
+
+        let resolution = resolver
+            .resolve(Some("com.example.app"), Some("Missing"), false)
+            .unwrap();
+        assert_eq!(resolution.identifier(), "com.example.app");
+        assert_eq!(resolution.source(), BundleSource::Explicit);
+
+        let resolution = resolver.resolve(None, Some("Example App"), false).unwrap();
+        assert_eq!(resolution.identifier(), "com.example.app");
+        assert_eq!(resolution.source(), BundleSource::AppName);
+
+        let resolution = resolver.resolve(None, Some("Missing"), true).unwrap();
+        assert_eq!(resolution.identifier(), FALLBACK_BUNDLE_IDENTIFIER);
+        assert!(resolution.is_fallback());
+        assert!(matches!(
+            resolver.resolve(None, Some("Missing"), false),
+            Err(NotificationError::AppNotFound(name)) if name == "Missing"
+        ));
+        assert!(matches!(
+            resolver.resolve(None, None, false),
+            Err(NotificationError::BundleNotResolved)
+        ));
+    }
+
+    #[test]
+    fn explicit_identifiers_are_never_replaced() {
+        let applications = Applications::new("explicit");
+        applications.write("Example.app", EXAMPLE);
+        let resolver = applications.resolver();
+
+        for fallback in [true, false] {
+            assert!(matches!(
+                resolver.resolve(Some("com.example.missing"), Some("Example"), fallback),
+                Err(NotificationError::AppNotFound(id)) if id == "com.example.missing"
+            ));
+        }
+    }
+}
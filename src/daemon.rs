@@ -0,0 +1,422 @@
+//! # Daemon
+//! Lets other processes send notifications through a `NotificationProvider` over a Unix domain socket.
+//!
+//! Clients write one JSON `Request` per line and get one JSON `Message` per line back.
+//! After a `subscribe` request the connection additionally receives every `NotificationEvent` as `event` message.
+//! Events are written as they happen, so they may arrive before the answer to the request which caused them.
+//! ```text
+//! > {"command":"send","notification":{"title":"Build finished","reply":true}}
+//! < {"type":"sent","identifier":"5f1c…"}
+//! > {"command":"subscribe"}
+//! < {"type":"subscribed"}
+//! < {"type":"event","event":{"type":"replied","identifier":"5f1c…","text":"ship it"}}
+//! ```
+//! The provider stays on its thread, the daemon only talks to it through a `NotificationHandle`.
+//! The `mac-notifyd` binary runs a daemon with the NSUserNotification backend.
+
+use crate::{Notification, NotificationEvent, NotificationHandle};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use uuid::Uuid;
+
+/// Longest line a client may send, the connection is closed after a longer one
+const MAX_LINE: usize = 64 * 1024;
+
+/// A line sent by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Sends the notification, answered with `Message::Sent`
+    Send { notification: Box<Notification> },
+    /// Deletes a delivered notification, answered with `Message::Deleted`
+    Delete { identifier: String },
+    /// Deletes all delivered notifications, answered with `Message::Deleted`
+    DeleteAll,
+    /// Lists the delivered notifications, answered with `Message::Notifications`
+    List,
+    /// Streams the events of all notifications to the connection, answered with `Message::Subscribed`
+    Subscribe,
+}
+
+/// A line sent by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Sent {
+        identifier: String,
+    },
+    Deleted,
+    Notifications {
+        notifications: Vec<Notification>,
+    },
+    Subscribed,
+    Event {
+        event: NotificationEvent,
+    },
+    /// The request could not be parsed or executed
+    Error {
+        message: String,
+    },
+}
+
+/// Executes a request through the handle, blocks until the provider executed it.
+/// `Request::Subscribe` is answered but needs a connection to forward the events, see `Daemon`
+pub fn execute(handle: &NotificationHandle, request: Request) -> Message {
+    let result = match request {
+        Request::Send { notification } => handle
+            .send(*notification)
+            .wait()
+            .and_then(|result| result)
+            .map(|identifier| Message::Sent { identifier }),
+        Request::Delete { identifier } => {
+            handle.delete(&identifier).wait().map(|_| Message::Deleted)
+        }
+        Request::DeleteAll => handle.delete_all().wait().map(|_| Message::Deleted),
+        Request::List => handle
+            .get_all_notifications()
+            .wait()
+            .map(|notifications| Message::Notifications { notifications }),
+        Request::Subscribe => Ok(Message::Subscribed),
+    };
+    result.unwrap_or_else(|err| Message::Error {
+        message: err.to_string(),
+    })
+}
+
+/// Parses a line of a client and executes it, malformed lines are answered with `Message::Error`
+pub fn execute_line(handle: &NotificationHandle, line: &str) -> Message {
+    match serde_json::from_str(line) {
+        Ok(request) => execute(handle, request),
+        Err(err) => Message::Error {
+            message: format!("invalid request: {}", err),
+        },
+    }
+}
+
+/// Listens on a Unix domain socket and serves every client on its own thread
+/// # Example
+/// ```rust
+/// use mac_notifications::backend::MockBackend;
+/// use mac_notifications::daemon::Daemon;
+/// use mac_notifications::NotificationProvider;
+/// use std::io::{BufRead, BufReader, Write};
+/// use std::os::unix::net::UnixStream;
+/// use std::thread;
+///
+/// let mock = MockBackend::new();
+/// let provider = NotificationProvider::with_backend(mock.clone());
+/// let path = std::env::temp_dir().join(format!("notifyd-{}.sock", std::process::id()));
+/// let daemon = Daemon::bind(&path, provider.handle()).unwrap();
+/// daemon.spawn();
+///
+/// let socket = path.clone();
+/// let client = thread::spawn(move || {
+///     let mut stream = UnixStream::connect(&socket).unwrap();
+///     let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+///     let mut next = || lines.next().unwrap().unwrap();
+///
+///     writeln!(stream, "hello").unwrap();
+///     assert!(next().starts_with(r#"{"type":"error""#));
+///     writeln!(stream, r#"{{"command":"subscribe"}}"#).unwrap();
+///     assert_eq!(next(), r#"{"type":"subscribed"}"#);
+///
+///     writeln!(stream, r#"{{"command":"send","notification":{{"title":"Hello"}}}}"#).unwrap();
+///     // the answer, the delivery and the click
+///     let received = [next(), next(), next()];
+///     assert!(received.iter().any(|line| line.starts_with(r#"{"type":"sent""#)));
+///     assert!(received.iter().any(|line| line.contains(r#""type":"activated""#)));
+/// });
+///
+/// // the provider keeps running on this thread
+/// let mut clicked = false;
+/// while !client.is_finished() {
+///     provider.run_main_loop_once();
+///     if let (false, Some(notification)) = (clicked, mock.sent().first()) {
+///         mock.click(notification.get_identifier());
+///         clicked = true;
+///     }
+/// }
+/// client.join().unwrap();
+/// assert_eq!(mock.sent()[0].get_title(), Some("Hello"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Daemon {
+    listener: UnixListener,
+    path: PathBuf,
+    handle: NotificationHandle,
+}
+
+impl Daemon {
+    /// Creates the socket, only the current user may connect to it.
+    /// A socket left behind by a daemon which is not running anymore is replaced,
+    /// fails with `io::ErrorKind::AddrInUse` if another daemon is listening or the path is not a socket
+    pub fn bind<P: AsRef<Path>>(path: P, handle: NotificationHandle) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.file_type().is_socket() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            Ok(_) if UnixStream::connect(&path).is_ok() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("a daemon is already listening on {}", path.display()),
+                ));
+            }
+            Ok(_) => fs::remove_file(&path)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        // the socket is created in a private directory and only moved into place once it is private itself
+        let directory = path.with_file_name(format!(".{}", Uuid::new_v4().simple()));
+        fs::DirBuilder::new().mode(0o700).create(&directory)?;
+        let socket = directory.join("socket");
+        let listener = UnixListener::bind(&socket).and_then(|listener| {
+            fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+            fs::rename(&socket, &path)?;
+            Ok(listener)
+        });
+        let _ = fs::remove_dir_all(&directory);
+        let listener = listener?;
+        Ok(Self {
+            listener,
+            path,
+            handle,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accepts clients until the listener fails
+    pub fn run(&self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let handle = self.handle.clone();
+            // a failed read or write only ends this connection
+            thread::spawn(move || serve(stream, handle));
+        }
+        Ok(())
+    }
+
+    /// Runs `run` on a new thread. The requests of the clients are executed by the provider,
+    /// so its thread has to keep calling `NotificationProvider::run_main_loop_once`
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn write_message(stream: &Mutex<UnixStream>, message: &Message) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    // subscriptions write from another thread, a line is never interleaved with another one
+    let mut stream = stream.lock().unwrap_or_else(|err| err.into_inner());
+    stream.write_all(&line)
+}
+
+fn serve(stream: UnixStream, handle: NotificationHandle) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let writer = Arc::new(Mutex::new(stream));
+    let mut subscribed = false;
+    loop {
+        let mut line = vec![];
+        if (&mut reader)
+            .take(MAX_LINE as u64 + 1)
+            .read_until(b'\n', &mut line)?
+            == 0
+        {
+            break;
+        }
+        if line.len() > MAX_LINE {
+            // the rest of the line can not be told apart from the next request
+            let message = format!("a request is longer than {} bytes", MAX_LINE);
+            return write_message(&writer, &Message::Error { message });
+        }
+        let line = String::from_utf8_lossy(&line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = execute_line(&handle, &line);
+        // answered before the events start
+        write_message(&writer, &message)?;
+        if matches!(message, Message::Subscribed) && !subscribed {
+            subscribed = true;
+            let events = handle.subscribe();
+            let writer = writer.clone();
+            // ends when the client disconnects or the provider is dropped
+            thread::spawn(move || {
+                for event in events {
+                    if write_message(&writer, &Message::Event { event }).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::NotificationProvider;
+
+    /// Executes the lines one after another on another thread while the provider runs on this one
+    fn exchange(provider: &NotificationProvider, lines: &[&str]) -> Vec<Message> {
+        let handle = provider.handle();
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        let client = thread::spawn(move || {
+            lines
+                .iter()
+                .map(|line| execute_line(&handle, line))
+                .collect()
+        });
+        while !client.is_finished() {
+            provider.process_commands();
+        }
+        client.join().unwrap()
+    }
+
+    fn error(message: &Message) -> &str {
+        match message {
+            Message::Error { message } => message,
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("notifyd-test-{}-{}.sock", name, std::process::id()))
+    }
+
+    #[test]
+    fn malformed_lines_are_answered_with_errors() {
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let messages = exchange(
+            &provider,
+            &[
+                "hello",
+                "{}",
+                r#"{"command":"reboot"}"#,
+                r#"{"command":"delete"}"#,
+                r#"{"command":"send","notification":{"delivery_date":"tomorrow"}}"#,
+            ],
+        );
+        assert_eq!(messages.len(), 5);
+        for message in messages.iter() {
+            assert!(error(message).starts_with("invalid request: "));
+        }
+        assert!(error(&messages[2]).contains("reboot"));
+        assert!(error(&messages[3]).contains("identifier"));
+    }
+
+    #[test]
+    fn requests_go_through_the_provider() {
+        let mock = MockBackend::new();
+        let provider = NotificationProvider::with_backend(mock.clone());
+        let messages = exchange(
+            &provider,
+            &[
+                r#"{"command":"send","notification":{"identifier":"build","title":"Build"}}"#,
+                r#"{"command":"send","notification":{"title":"Deploy"}}"#,
+                r#"{"command":"delete","identifier":"build"}"#,
+                r#"{"command":"list"}"#,
+                r#"{"command":"delete_all"}"#,
+                r#"{"command":"list"}"#,
+            ],
+        );
+        assert!(matches!(&messages[0], Message::Sent { identifier } if identifier == "build"));
+        assert!(matches!(messages[2], Message::Deleted));
+        match &messages[3] {
+            Message::Notifications { notifications } => {
+                assert_eq!(notifications.len(), 1);
+                assert_eq!(notifications[0].get_title(), Some("Deploy"));
+            }
+            other => panic!("expected notifications, got {:?}", other),
+        }
+        assert!(
+            matches!(&messages[5], Message::Notifications { notifications } if notifications.is_empty())
+        );
+        assert_eq!(mock.sent().len(), 2);
+    }
+
+    #[test]
+    fn execute_fails_without_provider() {
+        let handle = NotificationProvider::with_backend(MockBackend::new()).handle();
+        let message = execute_line(&handle, r#"{"command":"list"}"#);
+        assert_eq!(
+            error(&message),
+            crate::NotificationError::Disconnected.to_string()
+        );
+    }
+
+    #[test]
+    fn socket_is_private() {
+        let path = socket_path("private");
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let daemon = Daemon::bind(&path, provider.handle()).unwrap();
+        let mode = fs::metadata(daemon.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn long_lines_close_the_connection() {
+        let path = socket_path("long");
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let daemon = Daemon::bind(&path, provider.handle()).unwrap();
+        let directory = path.parent().unwrap();
+        let hidden = fs::read_dir(directory)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with('.'))
+            .filter(|entry| entry.path().join("socket").exists())
+            .count();
+        assert_eq!(hidden, 0, "the private directory was left behind");
+        daemon.spawn();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        let mut line = "a".repeat(MAX_LINE + 1);
+        line.push('\n');
+        stream.write_all(line.as_bytes()).unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let message: Message = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert!(error(&message).contains("longer than"));
+        assert!(lines.next().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn only_stale_sockets_are_replaced() {
+        let path = socket_path("stale");
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        // left behind by a crashed daemon
+        drop(UnixListener::bind(&path).unwrap());
+        let daemon = Daemon::bind(&path, provider.handle()).unwrap();
+
+        let err = Daemon::bind(&path, provider.handle()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        drop(daemon);
+
+        fs::write(&path, "not a socket").unwrap();
+        let err = Daemon::bind(&path, provider.handle()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+        fs::remove_file(&path).unwrap();
+    }
+}
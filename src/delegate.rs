@@ -1,4 +1,6 @@
-use crate::NotificationResponse;
+use crate::backend::EventCallback;
+use crate::notification::notification_identifier;
+use crate::{NotificationEvent, NotificationResponse};
 use objc2::msg_send_id;
 use objc2::mutability::MainThreadOnly;
 use objc2::rc::Id;
@@ -9,9 +11,8 @@ use objc2_foundation::{
     NSUserNotificationCenterDelegate,
 };
 
-type Callback = dyn Fn(String, NotificationResponse);
 pub(super) struct State {
-    callback: Box<Callback>,
+    callback: Box<EventCallback>,
 }
 
 declare_class! {
@@ -30,6 +31,17 @@ declare_class! {
     unsafe impl NSObjectProtocol for RustNotificationDelegate {}
 
     unsafe impl NSUserNotificationCenterDelegate for RustNotificationDelegate {
+        #[method(userNotificationCenter:didDeliverNotification:)]
+        fn did_deliver(
+            &self,
+            _center: &NSUserNotificationCenter,
+            notification: &NSUserNotification,
+        ) {
+            if let Some(id) = notification_identifier(notification) {
+                self.ivars().callback.as_ref()(NotificationEvent::Delivered { identifier: id });
+            }
+        }
+
         #[method(userNotificationCenter:didActivateNotification:)]
         fn did_activate(
             &self,
@@ -38,9 +50,11 @@ declare_class! {
         ) {
             let response = NotificationResponse::from_dictionary(notification);
 
-            let id = unsafe { notification.identifier() };
+            let id = notification_identifier(notification);
             match id {
-                Some(id) => self.ivars().callback.as_ref()(id.as_ref().to_string(), response),
+                Some(id) => self.ivars().callback.as_ref()(
+                    NotificationEvent::from_response(id, response),
+                ),
                 None => eprintln!("Notification has no identifier! This should never happen! Do you have another application providing notifications for the same app name?")
             }
         }
@@ -50,7 +64,7 @@ declare_class! {
 impl RustNotificationDelegate {
     pub fn new<F>(callback: F) -> Id<Self>
     where
-        F: Fn(String, NotificationResponse) + 'static,
+        F: Fn(NotificationEvent) + 'static,
     {
         let this = MainThreadMarker::new().unwrap().alloc().set_ivars(State {
             callback: Box::new(callback),
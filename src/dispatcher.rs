@@ -0,0 +1,141 @@
+use crate::notification::ResponseHandler;
+use crate::{NotificationEvent, NotificationResponse};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+#[cfg(feature = "async")]
+use std::task::Waker;
+
+type ResponseCallback = dyn Fn(String, NotificationResponse);
+
+/// Fans the events of a backend out to the callback, the subscribers and the per-notification handlers of a `NotificationProvider`
+#[derive(Default)]
+pub(crate) struct Dispatcher {
+    callback: RefCell<Option<Box<ResponseCallback>>>,
+    subscribers: RefCell<Vec<Sender<NotificationEvent>>>,
+    /// identifier of the notification -> handler attached with `Notification::on_response`
+    /// and whether the notification repeats
+    handlers: RefCell<HashMap<String, (ResponseHandler, bool)>>,
+    /// tasks waiting for the next event, see `stream::ResponseStream`
+    #[cfg(feature = "async")]
+    wakers: RefCell<Vec<Waker>>,
+}
+
+impl Dispatcher {
+    pub fn set_callback(&self, callback: Box<ResponseCallback>) {
+        *self.callback.borrow_mut() = Some(callback);
+    }
+
+    pub fn subscribe(&self, sender: Sender<NotificationEvent>) {
+        self.subscribers.borrow_mut().push(sender);
+    }
+
+    /// Handlers of recurring notifications are kept after a response, they are removed with `remove_handler`
+    pub fn register_handler(&self, identifier: &str, handler: ResponseHandler, recurring: bool) {
+        self.handlers
+            .borrow_mut()
+            .insert(identifier.to_string(), (handler, recurring));
+    }
+
+    pub fn remove_handler(&self, identifier: &str) {
+        self.handlers.borrow_mut().remove(identifier);
+    }
+
+    /// Removes all handlers except the ones of the given notifications
+    pub fn retain_handlers(&self, identifiers: &[String]) {
+        self.handlers
+            .borrow_mut()
+            .retain(|identifier, _| identifiers.contains(identifier));
+    }
+
+    /// Wakes the task with the next event
+    #[cfg(feature = "async")]
+    pub fn register_waker(&self, waker: &Waker) {
+        let mut wakers = self.wakers.borrow_mut();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    pub fn dispatch(&self, event: NotificationEvent) {
+        // dropped receivers unsubscribe
+        self.subscribers
+            .borrow_mut()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+
+        if let Some(response) = event.response() {
+            // a response ends the life of a notification unless it repeats
+            let handler = {
+                let mut handlers = self.handlers.borrow_mut();
+                match handlers.get(event.identifier()) {
+                    Some((handler, true)) => Some(handler.clone()),
+                    Some(_) => handlers
+                        .remove(event.identifier())
+                        .map(|(handler, _)| handler),
+                    None => None,
+                }
+            };
+            if let Some(handler) = handler {
+                handler.call(response.clone());
+            }
+            if let Some(callback) = self.callback.borrow().as_ref() {
+                callback(event.identifier().to_string(), response);
+            }
+        }
+        #[cfg(feature = "async")]
+        for waker in self.wakers.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::MockBackend;
+    use crate::{Notification, NotificationProvider, Recurrence};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn counting(notification: Notification) -> (Notification, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let calls = count.clone();
+        let notification = notification.on_response(move |_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+        });
+        (notification, count)
+    }
+
+    #[test]
+    fn handler_is_removed_after_the_first_response() {
+        let mock = MockBackend::new();
+        let _provider = NotificationProvider::with_backend(mock.clone());
+        let (notification, count) = counting(Notification::new().title("Once"));
+        let id = notification.send().unwrap();
+        mock.click(&id);
+        mock.click(&id);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn recurring_handler_is_kept_until_cancelled() {
+        let mock = MockBackend::new();
+        let provider = NotificationProvider::with_backend(mock.clone());
+        let (notification, count) = counting(
+            Notification::new()
+                .title("Drink water")
+                .recurrence(Recurrence::Hours(1)),
+        );
+        let id = notification.send().unwrap();
+        mock.deliver_scheduled();
+        mock.click(&id);
+        // the delivered repetition is removed, the next one is still scheduled
+        provider.delete(&id);
+        mock.deliver_scheduled();
+        mock.click(&id);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        provider.cancel_scheduled(&id).unwrap();
+        mock.click(&id);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::NotificationResponse;
+
+/// Everything that can happen to a notification after it was sent
+///
+/// With the `serde` feature events are serialized with their kind in the `type` field,
+/// e.g. `{"type":"replied","identifier":"…","text":"…"}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum NotificationEvent {
+    /// The notification was shown to the user
+    Delivered { identifier: String },
+    /// User clicked the notification directly
+    Activated { identifier: String },
+    /// User submitted text to the input text field
+    Replied { identifier: String, text: String },
+    /// User clicked on the action button with the given identifier
+    Action { identifier: String, action: String },
+    /// User removed the notification. `button` is the title of the close button if it was used
+    Dismissed {
+        identifier: String,
+        button: Option<String>,
+    },
+    /// The notification went away without any interaction
+    Expired { identifier: String },
+}
+
+impl NotificationEvent {
+    pub(crate) fn from_response(identifier: String, response: NotificationResponse) -> Self {
+        match response {
+            NotificationResponse::None => Self::Expired { identifier },
+            NotificationResponse::ActionButton(action) => Self::Action { identifier, action },
+            NotificationResponse::CloseButton(button) => Self::Dismissed {
+                identifier,
+                button: Some(button),
+            },
+            NotificationResponse::Dismissed => Self::Dismissed {
+                identifier,
+                button: None,
+            },
+            NotificationResponse::Click => Self::Activated { identifier },
+            NotificationResponse::Reply(text) => Self::Replied { identifier, text },
+        }
+    }
+
+    /// Identifier of the notification the event belongs to
+    pub fn identifier(&self) -> &str {
+        match self {
+            Self::Delivered { identifier }
+            | Self::Activated { identifier }
+            | Self::Replied { identifier, .. }
+            | Self::Action { identifier, .. }
+            | Self::Dismissed { identifier, .. }
+            | Self::Expired { identifier } => identifier,
+        }
+    }
+
+    /// The response of the user, `None` for `Delivered` events
+    pub fn response(&self) -> Option<NotificationResponse> {
+        match self {
+            Self::Delivered { .. } => None,
+            Self::Activated { .. } => Some(NotificationResponse::Click),
+            Self::Replied { text, .. } => Some(NotificationResponse::Reply(text.clone())),
+            Self::Action { action, .. } => Some(NotificationResponse::ActionButton(action.clone())),
+            Self::Dismissed {
+                button: Some(button),
+                ..
+            } => Some(NotificationResponse::CloseButton(button.clone())),
+            Self::Dismissed { button: None, .. } => Some(NotificationResponse::Dismissed),
+            Self::Expired { .. } => Some(NotificationResponse::None),
+        }
+    }
+}
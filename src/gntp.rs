@@ -0,0 +1,984 @@
+//! # GNTP
+//! A server for the [Growl Notification Transport Protocol](http://www.growlforwindows.com/gfw/help/gntp.aspx) 1.0,
+//! so tools which still send Growl notifications can send them through this crate.
+//!
+//! Applications register their notification types with `REGISTER` and send them with `NOTIFY`.
+//! A notification is mapped onto the `Notification` builder like this:
+//! - `Notification-Title` and `Notification-Text` become the title and the body
+//! - the name of the application becomes the subtitle
+//! - a `Notification-Priority` of 1 or 2 plays the default sound
+//! - the icon of the notification, its type or the application becomes the image,
+//!   binary resources (`x-growl-resource://`) are written to a directory only the current user can read,
+//!   they are removed together with the notification
+//! - a notification with the `Notification-Coalescing-ID` of an earlier one replaces it
+//!
+//! If the `NOTIFY` request has a `Notification-Callback-Context` the connection stays open
+//! until the user responded, which is reported as `-CALLBACK` with the result `CLICKED`, `CLOSED` or `TIMEDOUT`.
+//! With a `Notification-Callback-Target` the URL is opened when the notification is clicked instead,
+//! only `http`, `https` and `mailto` URLs are accepted.
+//!
+//! Encrypted messages are rejected and passwords are not checked, so the server should only listen on localhost.
+//! Clients have 10 seconds to send their message and at most 64 of them are served at the same time.
+
+use crate::opener::{self, open_url, UrlOpener};
+use crate::{Notification, NotificationHandle, NotificationResponse};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// The port Growl listens on
+pub const DEFAULT_PORT: u16 = 23053;
+
+/// Longer lines are rejected
+const MAX_LINE: usize = 64 * 1024;
+/// Bigger binary resources are rejected
+const MAX_RESOURCE: usize = 16 * 1024 * 1024;
+/// Clients served at the same time, including the ones waiting for a callback. Further clients are turned away
+const MAX_CONNECTIONS: usize = 64;
+/// Clients which do not send their message in time are disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const RESOURCE_SCHEME: &str = "x-growl-resource://";
+
+/// Error codes of the GNTP specification
+const INVALID_REQUEST: u16 = 300;
+const UNKNOWN_PROTOCOL: u16 = 301;
+const UNKNOWN_PROTOCOL_VERSION: u16 = 302;
+const REQUIRED_HEADER_MISSING: u16 = 303;
+const UNKNOWN_APPLICATION: u16 = 401;
+const UNKNOWN_NOTIFICATION: u16 = 402;
+const NOTIFICATION_DISABLED: u16 = 404;
+const INTERNAL_SERVER_ERROR: u16 = 500;
+
+/// An `-ERROR` answer
+#[derive(Debug)]
+struct Failure {
+    code: u16,
+    description: String,
+}
+
+impl Failure {
+    fn new(code: u16, description: impl Into<String>) -> Self {
+        Self {
+            code,
+            description: description.into(),
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Self {
+        Self::new(INVALID_REQUEST, err.to_string())
+    }
+}
+
+/// Header names are case-insensitive, they are stored in lower case
+type Headers = HashMap<String, String>;
+
+fn header<'a>(headers: &'a Headers, name: &str) -> Option<&'a str> {
+    headers.get(name).map(String::as_str)
+}
+
+fn required<'a>(headers: &'a Headers, name: &str) -> Result<&'a str, Failure> {
+    header(headers, &name.to_ascii_lowercase())
+        .ok_or_else(|| Failure::new(REQUIRED_HEADER_MISSING, format!("{} is missing", name)))
+}
+
+fn is_true(value: Option<&str>) -> bool {
+    value.is_some_and(|value| {
+        value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("yes")
+    })
+}
+
+/// Private directory of a server for the binary resources of the messages, removed with the server
+#[derive(Debug)]
+struct ResourceDir {
+    path: PathBuf,
+}
+
+impl ResourceDir {
+    fn create() -> io::Result<Self> {
+        let path = env::temp_dir().join(format!("gntp-{}", Uuid::new_v4()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&path)?;
+        Ok(Self { path })
+    }
+
+    /// Writes the data to a new file, a file which already exists is never opened
+    fn write(&self, data: &[u8]) -> io::Result<ResourceFile> {
+        let path = self.path.join(Uuid::new_v4().to_string());
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let resource = ResourceFile(path);
+        file.write_all(data)?;
+        Ok(resource)
+    }
+}
+
+impl Drop for ResourceDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A binary resource in the `ResourceDir`, the file is removed when the last reference is dropped.
+/// Registered applications and the response handlers of notifications keep the files they show
+#[derive(Debug)]
+struct ResourceFile(PathBuf);
+
+impl Drop for ResourceFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// A parsed request, the header blocks of the notification types of a `REGISTER` are in `sections`
+#[derive(Debug)]
+struct Message {
+    kind: String,
+    headers: Headers,
+    sections: Vec<Headers>,
+    /// identifier of a binary resource -> the file it was written to
+    resources: HashMap<String, Arc<ResourceFile>>,
+}
+
+impl Message {
+    /// Replaces a reference to a binary resource with the path of its file
+    fn resolve(&self, icon: Option<&str>) -> Option<String> {
+        let icon = icon?;
+        match icon.strip_prefix(RESOURCE_SCHEME) {
+            Some(identifier) => self
+                .resources
+                .get(identifier)
+                .map(|file| file.0.to_string_lossy().to_string()),
+            None => Some(icon.to_string()),
+        }
+    }
+}
+
+/// Reads a line without its line break, fails if it is longer than `MAX_LINE`
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = vec![];
+    if reader
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?
+        == 0
+    {
+        return Ok(None);
+    }
+    if line.len() > MAX_LINE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("a line is longer than {} bytes", MAX_LINE),
+        ));
+    }
+    while matches!(line.last(), Some(b'\n' | b'\r')) {
+        line.pop();
+    }
+    Ok(Some(String::from_utf8_lossy(&line).to_string()))
+}
+
+/// Reads `Name: value` lines up to the next blank line, blank lines in front of them are skipped
+fn read_headers(reader: &mut impl BufRead) -> Result<Headers, Failure> {
+    let mut headers = Headers::new();
+    loop {
+        let line = read_line(reader)?
+            .ok_or_else(|| Failure::new(INVALID_REQUEST, "the message ended early"))?;
+        if line.is_empty() && headers.is_empty() {
+            continue;
+        }
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        match line.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+            }
+            _ => {
+                return Err(Failure::new(
+                    INVALID_REQUEST,
+                    format!("invalid header: {}", line),
+                ))
+            }
+        }
+    }
+}
+
+fn read_message(reader: &mut impl BufRead, directory: &ResourceDir) -> Result<Message, Failure> {
+    let information =
+        read_line(reader)?.ok_or_else(|| Failure::new(INVALID_REQUEST, "the message is empty"))?;
+    let mut parts = information.split_whitespace();
+    let version = parts.next().unwrap_or_default();
+    let kind = parts.next().unwrap_or_default().to_ascii_uppercase();
+    let encryption = parts.next().unwrap_or_default();
+    match version.split_once('/') {
+        Some(("GNTP", "1.0")) => {}
+        Some(("GNTP", _)) => {
+            return Err(Failure::new(UNKNOWN_PROTOCOL_VERSION, version));
+        }
+        _ => return Err(Failure::new(UNKNOWN_PROTOCOL, version)),
+    }
+    if !encryption.eq_ignore_ascii_case("NONE") {
+        return Err(Failure::new(INVALID_REQUEST, "encryption is not supported"));
+    }
+
+    let headers = read_headers(reader)?;
+    let mut sections = vec![];
+    if kind == "REGISTER" {
+        let count: usize = required(&headers, "Notifications-Count")?
+            .parse()
+            .map_err(|_| Failure::new(INVALID_REQUEST, "invalid Notifications-Count"))?;
+        for _ in 0..count {
+            sections.push(read_headers(reader)?);
+        }
+    }
+
+    // every referenced resource follows as identifier, length and data
+    let referenced: HashSet<&str> = sections
+        .iter()
+        .chain([&headers])
+        .flat_map(|headers| headers.values())
+        .filter_map(|value| value.strip_prefix(RESOURCE_SCHEME))
+        .collect();
+    let mut resources = HashMap::new();
+    for _ in 0..referenced.len() {
+        let resource = read_headers(reader)?;
+        let identifier = required(&resource, "Identifier")?.to_string();
+        let length: usize = required(&resource, "Length")?
+            .parse()
+            .ok()
+            .filter(|length| *length <= MAX_RESOURCE)
+            .ok_or_else(|| Failure::new(INVALID_REQUEST, "invalid resource length"))?;
+        let mut data = vec![0; length];
+        reader.read_exact(&mut data)?;
+        // the line break after the data, the blank line is skipped by the next `read_headers`
+        read_line(reader)?;
+        let file = directory
+            .write(&data)
+            .map_err(|err| Failure::new(INTERNAL_SERVER_ERROR, err.to_string()))?;
+        resources.insert(identifier, Arc::new(file));
+    }
+    Ok(Message {
+        kind,
+        headers,
+        sections,
+        resources,
+    })
+}
+
+fn write_message(stream: &mut impl Write, kind: &str, headers: &[(&str, &str)]) -> io::Result<()> {
+    let mut message = format!("GNTP/1.0 {} NONE\r\n", kind);
+    for (name, value) in headers {
+        message.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    message.push_str("\r\n");
+    stream.write_all(message.as_bytes())
+}
+
+fn write_failure(stream: &mut impl Write, failure: &Failure) -> io::Result<()> {
+    write_message(
+        stream,
+        "-ERROR",
+        &[
+            ("Error-Code", &failure.code.to_string()),
+            ("Error-Description", &failure.description),
+        ],
+    )
+}
+
+/// A client counted against the limit of its server until the slot is dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn take(connections: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .ok()?;
+        Some(Self(connections.clone()))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The result of a `-CALLBACK` for the response of the user
+fn callback_result(response: &NotificationResponse) -> &'static str {
+    match response {
+        NotificationResponse::Click
+        | NotificationResponse::ActionButton(_)
+        | NotificationResponse::Reply(_) => "CLICKED",
+        NotificationResponse::CloseButton(_) | NotificationResponse::Dismissed => "CLOSED",
+        NotificationResponse::None => "TIMEDOUT",
+    }
+}
+
+#[derive(Debug, Clone)]
+struct NotificationType {
+    enabled: bool,
+    icon: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Application {
+    icon: Option<String>,
+    types: HashMap<String, NotificationType>,
+    /// keeps the files of the icons until the application registers again
+    _resources: Vec<Arc<ResourceFile>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    applications: HashMap<String, Application>,
+    /// coalescing identifier -> identifier of the notification which is shown
+    coalesced: HashMap<String, String>,
+}
+
+/// The answer to a `NOTIFY` and the response of the user, if it is reported back
+struct Notified {
+    identifier: String,
+    callback: Option<mpsc::Receiver<NotificationResponse>>,
+}
+
+/// Receives GNTP messages and sends the notifications through a `NotificationProvider`
+/// # Example
+/// ```rust
+/// use mac_notifications::backend::MockBackend;
+/// use mac_notifications::gntp::GntpServer;
+/// use mac_notifications::NotificationProvider;
+/// use std::io::{BufRead, BufReader, Write};
+/// use std::net::{SocketAddr, TcpStream};
+/// use std::thread;
+///
+/// /// Sends the message and reads the first answer, the stream stays open for callbacks
+/// fn request(address: SocketAddr, message: &str) -> (String, BufReader<TcpStream>) {
+///     let mut stream = TcpStream::connect(address).unwrap();
+///     stream.write_all(message.replace('\n', "\r\n").as_bytes()).unwrap();
+///     let mut reader = BufReader::new(stream);
+///     (read_answer(&mut reader), reader)
+/// }
+///
+/// fn read_answer(reader: &mut BufReader<TcpStream>) -> String {
+///     let mut answer = String::new();
+///     while !answer.ends_with("\r\n\r\n") {
+///         reader.read_line(&mut answer).unwrap();
+///     }
+///     answer
+/// }
+///
+/// let mock = MockBackend::new();
+/// let provider = NotificationProvider::with_backend(mock.clone());
+/// let server = GntpServer::bind("127.0.0.1:0", provider.handle()).unwrap();
+/// let address = server.local_addr().unwrap();
+/// server.spawn();
+///
+/// let client = thread::spawn(move || {
+///     let (answer, _) = request(
+///         address,
+///         "GNTP/1.0 REGISTER NONE
+/// Application-Name: Jenkins
+/// Notifications-Count: 1
+///
+/// Notification-Name: build
+/// Notification-Enabled: True
+///
+/// ",
+///     );
+///     assert!(answer.starts_with("GNTP/1.0 -OK NONE"));
+///
+///     let (answer, mut callbacks) = request(
+///         address,
+///         "GNTP/1.0 NOTIFY NONE
+/// Application-Name: Jenkins
+/// Notification-Name: build
+/// Notification-Title: Build failed
+/// Notification-Text: web-1 is red
+/// Notification-Callback-Context: build-1
+/// Notification-Callback-Context-Type: string
+///
+/// ",
+///     );
+///     assert!(answer.starts_with("GNTP/1.0 -OK NONE"));
+///     // the connection stays open until the user responded
+///     let callback = read_answer(&mut callbacks);
+///     assert!(callback.starts_with("GNTP/1.0 -CALLBACK NONE"));
+///     assert!(callback.contains("Notification-Callback-Result: CLICKED"));
+///     assert!(callback.contains("Notification-Callback-Context: build-1"));
+///
+///     let (answer, _) = request(
+///         address,
+///         "GNTP/1.0 NOTIFY NONE
+/// Application-Name: Unknown
+/// Notification-Name: build
+/// Notification-Title: Hello
+///
+/// ",
+///     );
+///     assert!(answer.contains("Error-Code: 401"));
+/// });
+///
+/// let mut clicked = false;
+/// while !client.is_finished() {
+///     provider.run_main_loop_once();
+///     if let (false, Some(notification)) = (clicked, mock.sent().first()) {
+///         mock.click(notification.get_identifier());
+///         clicked = true;
+///     }
+/// }
+/// client.join().unwrap();
+/// assert_eq!(mock.sent()[0].get_title(), Some("Build failed"));
+/// assert_eq!(mock.sent()[0].get_subtitle(), Some("Jenkins"));
+/// ```
+pub struct GntpServer {
+    listener: TcpListener,
+    handle: NotificationHandle,
+    registry: Arc<Mutex<Registry>>,
+    resources: Arc<ResourceDir>,
+    opener: Arc<UrlOpener>,
+    connections: Arc<AtomicUsize>,
+    max_connections: usize,
+}
+
+impl GntpServer {
+    /// Listens on the address, usually `("127.0.0.1", DEFAULT_PORT)` where Growl clients look for it.
+    /// Also creates the private directory for the binary resources of the messages
+    pub fn bind<A: ToSocketAddrs>(address: A, handle: NotificationHandle) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            handle,
+            registry: Arc::default(),
+            resources: Arc::new(ResourceDir::create()?),
+            opener: Arc::new(open_url),
+            connections: Arc::default(),
+            max_connections: MAX_CONNECTIONS,
+        })
+    }
+
+    /// Replaces how the `Notification-Callback-Target` of clicked notifications is opened
+    pub fn url_opener<F>(mut self, opener: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.opener = Arc::new(opener);
+        self
+    }
+
+    /// The address Growl clients send to, with the actual port if the server was bound to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Accepts clients until the listener fails, every client is served on its own thread.
+    /// At most 64 clients are served at the same time, further ones get an error
+    pub fn run(&self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let mut stream = stream?;
+            let Some(slot) = Slot::take(&self.connections, self.max_connections) else {
+                let failure = Failure::new(INTERNAL_SERVER_ERROR, "too many connections");
+                let _ = write_failure(&mut stream, &failure);
+                continue;
+            };
+            let connection = Connection {
+                handle: self.handle.clone(),
+                registry: self.registry.clone(),
+                resources: self.resources.clone(),
+                opener: self.opener.clone(),
+            };
+            // a client which hung up only ends its own thread
+            thread::spawn(move || {
+                let _slot = slot;
+                connection.serve(stream)
+            });
+        }
+        Ok(())
+    }
+
+    /// Runs `run` on a new thread. Notifications are only sent and callbacks only answered
+    /// while the thread of the provider runs its main loop
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+/// What a thread serving a client shares with the server
+struct Connection {
+    handle: NotificationHandle,
+    registry: Arc<Mutex<Registry>>,
+    resources: Arc<ResourceDir>,
+    opener: Arc<UrlOpener>,
+}
+
+impl Connection {
+    fn serve(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let result = read_message(&mut reader, &self.resources).and_then(|message| {
+            match message.kind.as_str() {
+                "REGISTER" => self.register(&message).map(|_| None),
+                "NOTIFY" => self
+                    .notify(&message)
+                    .map(|notified| Some((message, notified))),
+                kind => Err(Failure::new(
+                    INVALID_REQUEST,
+                    format!("{} is not supported", kind),
+                )),
+            }
+        });
+        match result {
+            Ok(None) => write_message(&mut stream, "-OK", &[("Response-Action", "REGISTER")]),
+            Ok(Some((message, notified))) => {
+                let id =
+                    header(&message.headers, "notification-id").unwrap_or(&notified.identifier);
+                write_message(
+                    &mut stream,
+                    "-OK",
+                    &[("Response-Action", "NOTIFY"), ("Notification-ID", id)],
+                )?;
+                let Some(callback) = notified.callback else {
+                    return Ok(());
+                };
+                // the response is dropped if the notification is deleted before
+                let Ok(response) = callback.recv() else {
+                    return Ok(());
+                };
+                let timestamp =
+                    chrono::DateTime::<chrono::Utc>::from(SystemTime::now()).to_rfc3339();
+                let mut headers = vec![
+                    ("Response-Action", "NOTIFY"),
+                    ("Notification-ID", id),
+                    ("Notification-Callback-Result", callback_result(&response)),
+                    ("Notification-Callback-Timestamp", &timestamp),
+                ];
+                for name in [
+                    "Notification-Callback-Context",
+                    "Notification-Callback-Context-Type",
+                ] {
+                    if let Some(value) = header(&message.headers, &name.to_ascii_lowercase()) {
+                        headers.push((name, value));
+                    }
+                }
+                write_message(&mut stream, "-CALLBACK", &headers)
+            }
+            Err(failure) => write_failure(&mut stream, &failure),
+        }
+    }
+
+    fn register(&self, message: &Message) -> Result<(), Failure> {
+        let name = required(&message.headers, "Application-Name")?;
+        let mut application = Application {
+            icon: message.resolve(header(&message.headers, "application-icon")),
+            types: HashMap::new(),
+            _resources: message.resources.values().cloned().collect(),
+        };
+        for section in message.sections.iter() {
+            application.types.insert(
+                required(section, "Notification-Name")?.to_string(),
+                NotificationType {
+                    enabled: is_true(header(section, "notification-enabled")),
+                    icon: message.resolve(header(section, "notification-icon")),
+                },
+            );
+        }
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        registry.applications.insert(name.to_string(), application);
+        Ok(())
+    }
+
+    fn notify(&self, message: &Message) -> Result<Notified, Failure> {
+        let headers = &message.headers;
+        let name = required(headers, "Application-Name")?;
+        let kind = required(headers, "Notification-Name")?;
+        let title = required(headers, "Notification-Title")?;
+        let (application, notification_type) = {
+            let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+            let application = registry.applications.get(name).cloned().ok_or_else(|| {
+                Failure::new(UNKNOWN_APPLICATION, format!("{} is not registered", name))
+            })?;
+            let notification_type = application.types.get(kind).cloned().ok_or_else(|| {
+                Failure::new(UNKNOWN_NOTIFICATION, format!("{} is not registered", kind))
+            })?;
+            (application, notification_type)
+        };
+        if !notification_type.enabled {
+            return Err(Failure::new(
+                NOTIFICATION_DISABLED,
+                format!("{} is disabled", kind),
+            ));
+        }
+
+        let mut notification = Notification::new().title(title).subtitle(name);
+        if let Some(text) = header(headers, "notification-text") {
+            notification = notification.body(text);
+        }
+        let priority: i8 = header(headers, "notification-priority")
+            .and_then(|priority| priority.parse().ok())
+            .unwrap_or(0);
+        if priority > 0 {
+            notification = notification.sound("default");
+        }
+        let icon = message
+            .resolve(header(headers, "notification-icon"))
+            .or(notification_type.icon)
+            .or(application.icon);
+        if let Some(icon) = icon.as_deref() {
+            notification = notification.image(icon);
+        }
+
+        let target = header(headers, "notification-callback-target").map(str::to_string);
+        if let Some(target) = target
+            .as_deref()
+            .filter(|target| !opener::is_allowed(target))
+        {
+            return Err(Failure::new(
+                INVALID_REQUEST,
+                format!("unsupported Notification-Callback-Target: {}", target),
+            ));
+        }
+        let (sender, callback) =
+            match target.is_none() && header(headers, "notification-callback-context").is_some() {
+                true => {
+                    let (sender, receiver) = mpsc::channel();
+                    (Some(sender), Some(receiver))
+                }
+                false => (None, None),
+            };
+        // the handler is dropped together with the notification and with it the resources it shows
+        let resources: Vec<Arc<ResourceFile>> = message.resources.values().cloned().collect();
+        if target.is_some() || sender.is_some() || !resources.is_empty() {
+            let opener = self.opener.clone();
+            notification = notification.on_response(move |response| {
+                let _resources = &resources;
+                if let Some(target) = target.as_deref() {
+                    if callback_result(&response) == "CLICKED" {
+                        opener(target);
+                    }
+                }
+                if let Some(sender) = sender.as_ref() {
+                    let _ = sender.send(response);
+                }
+            });
+        }
+
+        let coalescing = header(headers, "notification-coalescing-id");
+        let replaced = coalescing.and_then(|coalescing| self.coalesced(coalescing));
+        if let Some(replaced) = replaced {
+            let _ = self.handle.delete(&replaced).wait();
+        }
+        let identifier = self
+            .handle
+            .send(notification)
+            .wait()
+            .and_then(|result| result)
+            .map_err(|err| Failure::new(INTERNAL_SERVER_ERROR, err.to_string()))?;
+        if let Some(coalescing) = coalescing {
+            let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+            registry
+                .coalesced
+                .insert(coalescing.to_string(), identifier.clone());
+        }
+        Ok(Notified {
+            identifier,
+            callback,
+        })
+    }
+
+    /// The notification shown for the coalescing identifier.
+    /// Identifiers of notifications which are not shown anymore are removed on the way
+    fn coalesced(&self, coalescing: &str) -> Option<String> {
+        let delivered: HashSet<String> = self
+            .handle
+            .get_all_notifications()
+            .wait()
+            .ok()?
+            .iter()
+            .map(|notification| notification.get_identifier().to_string())
+            .collect();
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        registry
+            .coalesced
+            .retain(|_, identifier| delivered.contains(identifier));
+        registry.coalesced.get(coalescing).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::NotificationProvider;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    fn parse(message: &str, directory: &ResourceDir) -> Result<Message, Failure> {
+        read_message(&mut Cursor::new(message.replace('\n', "\r\n")), directory)
+    }
+
+    fn failure(message: &str) -> Failure {
+        let directory = ResourceDir::create().unwrap();
+        parse(message, &directory).unwrap_err()
+    }
+
+    fn connection(provider: &NotificationProvider) -> Connection {
+        Connection {
+            handle: provider.handle(),
+            registry: Arc::default(),
+            resources: Arc::new(ResourceDir::create().unwrap()),
+            opener: Arc::new(|_: &str| {}),
+        }
+    }
+
+    const REGISTER: &str = "GNTP/1.0 REGISTER NONE
+Application-Name: Jenkins
+Notifications-Count: 1
+
+Notification-Name: build
+Notification-Enabled: True
+
+";
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let missing_colon = failure("GNTP/1.0 NOTIFY NONE\nApplication-Name Jenkins\n\n");
+        assert_eq!(missing_colon.code, INVALID_REQUEST);
+        assert_eq!(
+            missing_colon.description,
+            "invalid header: Application-Name Jenkins"
+        );
+        let missing_name = failure("GNTP/1.0 NOTIFY NONE\n: Jenkins\n\n");
+        assert_eq!(missing_name.code, INVALID_REQUEST);
+
+        assert_eq!(failure("HTTP/1.1 NOTIFY NONE\n\n").code, UNKNOWN_PROTOCOL);
+        assert_eq!(
+            failure("GNTP/2.0 NOTIFY NONE\n\n").code,
+            UNKNOWN_PROTOCOL_VERSION
+        );
+        assert_eq!(
+            failure("GNTP/1.0 NOTIFY AES:1234\n\n").code,
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            failure("GNTP/1.0 NOTIFY NONE\nApplication-Name: Jenkins\n").description,
+            "the message ended early"
+        );
+        let count = failure("GNTP/1.0 REGISTER NONE\nNotifications-Count: many\n\n");
+        assert_eq!(count.description, "invalid Notifications-Count");
+    }
+
+    #[test]
+    fn over_long_lines_are_rejected() {
+        // the rest of the line must not be read as a header of its own
+        let text = format!("{}Application-Name: Other", "a".repeat(MAX_LINE));
+        let message = format!("GNTP/1.0 NOTIFY NONE\nNotification-Text: {}\n\n", text);
+        let failure = failure(&message);
+        assert_eq!(failure.code, INVALID_REQUEST);
+        assert!(failure.description.contains("longer than"));
+
+        let text = "a".repeat(MAX_LINE - "Notification-Text: \r\n".len());
+        let message = format!("GNTP/1.0 NOTIFY NONE\nNotification-Text: {}\n\n", text);
+        let directory = ResourceDir::create().unwrap();
+        let message = parse(&message, &directory).unwrap();
+        assert_eq!(header(&message.headers, "notification-text"), Some(&*text));
+    }
+
+    #[test]
+    fn resources_are_private_files() {
+        let directory = ResourceDir::create().unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&directory.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        // both identifiers used to end up in the same file
+        let message = parse(
+            "GNTP/1.0 NOTIFY NONE
+Application-Icon: x-growl-resource://!!!
+Notification-Icon: x-growl-resource://???
+
+Identifier: !!!
+Length: 3
+
+one
+
+Identifier: ???
+Length: 3
+
+two
+
+",
+            &directory,
+        )
+        .unwrap();
+        let first = message.resolve(Some("x-growl-resource://!!!")).unwrap();
+        let second = message.resolve(Some("x-growl-resource://???")).unwrap();
+        assert_ne!(first, second);
+        assert!(first.starts_with(directory.path.to_str().unwrap()));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "one");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "two");
+
+        drop(message);
+        assert!(!Path::new(&first).exists() && !Path::new(&second).exists());
+        let path = directory.path.clone();
+        drop(directory);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn resources_of_rejected_notifications_are_removed() {
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let connection = connection(&provider);
+        let message = parse(
+            "GNTP/1.0 NOTIFY NONE
+Application-Name: Unknown
+Notification-Name: build
+Notification-Title: Build failed
+Notification-Icon: x-growl-resource://icon
+
+Identifier: icon
+Length: 4
+
+icon
+
+",
+            &connection.resources,
+        )
+        .unwrap();
+        let icon = message.resolve(Some("x-growl-resource://icon")).unwrap();
+        let failure = connection.notify(&message).err().unwrap();
+        assert_eq!(failure.code, UNKNOWN_APPLICATION);
+        drop(message);
+        assert!(!Path::new(&icon).exists());
+    }
+
+    #[test]
+    fn callback_targets_are_checked() {
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let connection = connection(&provider);
+        connection
+            .register(&parse(REGISTER, &connection.resources).unwrap())
+            .unwrap();
+        for target in ["file:///Applications/Calculator.app", "javascript:alert(1)"] {
+            let message = format!(
+                "GNTP/1.0 NOTIFY NONE
+Application-Name: Jenkins
+Notification-Name: build
+Notification-Title: Build failed
+Notification-Callback-Target: {}
+
+",
+                target
+            );
+            let message = parse(&message, &connection.resources).unwrap();
+            let failure = connection.notify(&message).err().unwrap();
+            assert_eq!(failure.code, INVALID_REQUEST);
+            assert!(failure.description.contains(target));
+        }
+    }
+
+    /// Runs `notify` on another thread while the provider runs on this one
+    fn notify(
+        provider: &NotificationProvider,
+        connection: &Connection,
+        message: &str,
+    ) -> Result<Notified, Failure> {
+        let message = parse(message, &connection.resources).unwrap();
+        thread::scope(|scope| {
+            let worker = scope.spawn(|| connection.notify(&message));
+            while !worker.is_finished() {
+                provider.process_commands();
+            }
+            worker.join().unwrap()
+        })
+    }
+
+    fn coalesced(connection: &Connection) -> HashMap<String, String> {
+        connection.registry.lock().unwrap().coalesced.clone()
+    }
+
+    #[test]
+    fn coalescing_identifiers_are_removed_with_their_notification() {
+        let mock = MockBackend::new();
+        let provider = NotificationProvider::with_backend(mock.clone());
+        let connection = connection(&provider);
+        connection
+            .register(&parse(REGISTER, &connection.resources).unwrap())
+            .unwrap();
+        let message = |coalescing: &str| {
+            format!(
+                "GNTP/1.0 NOTIFY NONE
+Application-Name: Jenkins
+Notification-Name: build
+Notification-Title: Build
+Notification-Coalescing-ID: {}
+
+",
+                coalescing
+            )
+        };
+
+        let first = notify(&provider, &connection, &message("a")).unwrap();
+        mock.dismiss(&first.identifier);
+        let second = notify(&provider, &connection, &message("b")).unwrap();
+        assert_eq!(
+            coalesced(&connection),
+            HashMap::from([("b".to_string(), second.identifier.clone())])
+        );
+
+        let third = notify(&provider, &connection, &message("b")).unwrap();
+        assert_eq!(
+            coalesced(&connection),
+            HashMap::from([("b".to_string(), third.identifier.clone())])
+        );
+        let delivered = provider.get_all_notifications();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].get_identifier(), third.identifier);
+    }
+
+    #[test]
+    fn connections_are_limited() {
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let mut server = GntpServer::bind("127.0.0.1:0", provider.handle()).unwrap();
+        server.max_connections = 1;
+        let address = server.local_addr().unwrap();
+        server.spawn();
+
+        let answer = |stream: &mut TcpStream| {
+            stream
+                .write_all(b"GNTP/1.0 HELLO NONE\r\nApplication-Name: Test\r\n\r\n")
+                .unwrap();
+            let mut answer = String::new();
+            let _ = stream.read_to_string(&mut answer);
+            answer
+        };
+        // waits for its message like a client waiting for a callback
+        let first = TcpStream::connect(address).unwrap();
+        let mut second = String::new();
+        let _ = TcpStream::connect(address)
+            .unwrap()
+            .read_to_string(&mut second);
+        assert!(second.contains("too many connections"), "{}", second);
+
+        drop(first);
+        let accepted = (0..100).any(|_| {
+            thread::sleep(Duration::from_millis(20));
+            answer(&mut TcpStream::connect(address).unwrap()).contains("HELLO is not supported")
+        });
+        assert!(accepted);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{Notification, NotificationError, NotificationEvent, NotificationProvider};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, SystemTime};
+
+/// Work a `NotificationHandle` queues for the thread of the `NotificationProvider`
+pub(crate) enum Command {
+    Send(Box<Notification>, Sender<Result<String, NotificationError>>),
+    Delete(String, Sender<()>),
+    DeleteAll(Sender<()>),
+    GetAll(Sender<Vec<Notification>>),
+    GetScheduled(Sender<Vec<Notification>>),
+    CancelScheduled(String, Sender<Result<(), NotificationError>>),
+    CancelAllScheduled(Sender<Result<(), NotificationError>>),
+    Reschedule(String, SystemTime, Sender<Result<(), NotificationError>>),
+    Subscribe(Sender<NotificationEvent>),
+    #[cfg(target_os = "macos")]
+    SetBadge(Option<String>, Sender<()>),
+}
+
+/// Executes a queued command, the result is dropped if nobody waits for it anymore
+pub(crate) fn execute(provider: &NotificationProvider, command: Command) {
+    match command {
+        Command::Send(notification, reply) => {
+            let _ = reply.send(provider.send(*notification));
+        }
+        Command::Delete(identifier, reply) => {
+            provider.delete(&identifier);
+            let _ = reply.send(());
+        }
+        Command::DeleteAll(reply) => {
+            provider.delete_all();
+            let _ = reply.send(());
+        }
+        Command::GetAll(reply) => {
+            let _ = reply.send(provider.get_all_notifications());
+        }
+        Command::GetScheduled(reply) => {
+            let _ = reply.send(provider.get_scheduled_notifications());
+        }
+        Command::CancelScheduled(identifier, reply) => {
+            let _ = reply.send(provider.cancel_scheduled(&identifier));
+        }
+        Command::CancelAllScheduled(reply) => {
+            let _ = reply.send(provider.cancel_all_scheduled());
+        }
+        Command::Reschedule(identifier, delivery_date, reply) => {
+            let _ = reply.send(provider.reschedule(&identifier, delivery_date));
+        }
+        Command::Subscribe(sender) => provider.add_subscriber(sender),
+        #[cfg(target_os = "macos")]
+        Command::SetBadge(content, reply) => {
+            crate::misc::set_badge(content.as_deref());
+            let _ = reply.send(());
+        }
+    }
+}
+
+/// A handle to a `NotificationProvider` which can be sent to and shared between threads.
+///
+/// Every call is queued and executed on the thread of the provider the next time
+/// `NotificationProvider::run_main_loop_once` or `NotificationProvider::process_commands` is called.
+/// The result is returned as a `Reply`. Created with `NotificationProvider::handle`
+#[derive(Clone, Debug)]
+pub struct NotificationHandle {
+    sender: Sender<Command>,
+}
+
+impl NotificationHandle {
+    pub(crate) fn new(sender: Sender<Command>) -> Self {
+        Self { sender }
+    }
+
+    fn request<T>(&self, command: impl FnOnce(Sender<T>) -> Command) -> Reply<T> {
+        let (sender, receiver) = mpsc::channel();
+        // if the provider is gone the reply sender is dropped right away and `Reply` reports it
+        let _ = self.sender.send(command(sender));
+        Reply { receiver }
+    }
+
+    /// Sends the notification through the provider, the reply contains its identifier
+    pub fn send(&self, notification: Notification) -> Reply<Result<String, NotificationError>> {
+        self.request(|reply| Command::Send(Box::new(notification), reply))
+    }
+
+    /// Deletes a notification by its identifier
+    pub fn delete(&self, identifier: &str) -> Reply<()> {
+        let identifier = identifier.to_string();
+        self.request(|reply| Command::Delete(identifier, reply))
+    }
+
+    /// Deletes all delivered notifications
+    pub fn delete_all(&self) -> Reply<()> {
+        self.request(Command::DeleteAll)
+    }
+
+    /// Returns all delivered notifications
+    pub fn get_all_notifications(&self) -> Reply<Vec<Notification>> {
+        self.request(Command::GetAll)
+    }
+
+    /// Returns all notifications waiting for their delivery date
+    pub fn get_scheduled_notifications(&self) -> Reply<Vec<Notification>> {
+        self.request(Command::GetScheduled)
+    }
+
+    /// Cancels a scheduled notification before it is delivered
+    pub fn cancel_scheduled(&self, identifier: &str) -> Reply<Result<(), NotificationError>> {
+        let identifier = identifier.to_string();
+        self.request(|reply| Command::CancelScheduled(identifier, reply))
+    }
+
+    /// Cancels all scheduled notifications, delivered notifications are not affected
+    pub fn cancel_all_scheduled(&self) -> Reply<Result<(), NotificationError>> {
+        self.request(Command::CancelAllScheduled)
+    }
+
+    /// Moves a scheduled notification to a new delivery date, see `NotificationProvider::reschedule`
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider};
+    /// use std::thread;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let provider = NotificationProvider::with_backend(MockBackend::new());
+    /// let handle = provider.handle();
+    /// let in_an_hour = SystemTime::now() + Duration::from_secs(3600);
+    ///
+    /// let worker = thread::spawn(move || {
+    ///     let reminder = Notification::new().title("Standup").delivery_date(in_an_hour);
+    ///     let id = handle.send(reminder).wait().unwrap().unwrap();
+    ///     let later = in_an_hour + Duration::from_secs(600);
+    ///     handle.reschedule(&id, later).wait().unwrap().unwrap();
+    ///     let scheduled = handle.get_scheduled_notifications().wait().unwrap();
+    ///     assert_eq!(scheduled[0].get_delivery_date(), Some(later));
+    ///
+    ///     handle.cancel_scheduled(&id).wait().unwrap().unwrap();
+    ///     handle.get_scheduled_notifications().wait().unwrap()
+    /// });
+    /// while !worker.is_finished() {
+    ///     provider.run_main_loop_once();
+    /// }
+    /// assert!(worker.join().unwrap().is_empty());
+    /// ```
+    pub fn reschedule(
+        &self,
+        identifier: &str,
+        delivery_date: SystemTime,
+    ) -> Reply<Result<(), NotificationError>> {
+        let identifier = identifier.to_string();
+        self.request(|reply| Command::Reschedule(identifier, delivery_date, reply))
+    }
+
+    /// Receives the events of all notifications like `NotificationProvider::subscribe`.
+    /// Events are only received once the provider executed the command
+    pub fn subscribe(&self) -> Receiver<NotificationEvent> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.sender.send(Command::Subscribe(sender));
+        receiver
+    }
+
+    /// Sets the badge on the application icon in the Dock, see `misc::set_badge`
+    #[cfg(target_os = "macos")]
+    pub fn set_badge(&self, content: Option<&str>) -> Reply<()> {
+        let content = content.map(|s| s.to_string());
+        self.request(|reply| Command::SetBadge(content, reply))
+    }
+}
+
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Send(notification, _) => f.debug_tuple("Send").field(notification).finish(),
+            Command::Delete(identifier, _) => f.debug_tuple("Delete").field(identifier).finish(),
+            Command::DeleteAll(_) => f.write_str("DeleteAll"),
+            Command::GetAll(_) => f.write_str("GetAll"),
+            Command::GetScheduled(_) => f.write_str("GetScheduled"),
+            Command::CancelScheduled(identifier, _) => {
+                f.debug_tuple("CancelScheduled").field(identifier).finish()
+            }
+            Command::CancelAllScheduled(_) => f.write_str("CancelAllScheduled"),
+            Command::Reschedule(identifier, delivery_date, _) => f
+                .debug_tuple("Reschedule")
+                .field(identifier)
+                .field(delivery_date)
+                .finish(),
+            Command::Subscribe(_) => f.write_str("Subscribe"),
+            #[cfg(target_os = "macos")]
+            Command::SetBadge(content, _) => f.debug_tuple("SetBadge").field(content).finish(),
+        }
+    }
+}
+
+/// The pending result of a command queued by a `NotificationHandle`
+#[derive(Debug)]
+pub struct Reply<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Reply<T> {
+    /// Blocks until the provider executed the command.
+    /// Fails with `NotificationError::Disconnected` if the provider was dropped before
+    pub fn wait(self) -> Result<T, NotificationError> {
+        self.receiver
+            .recv()
+            .map_err(|_| NotificationError::Disconnected)
+    }
+
+    /// Like `wait`, but fails with `NotificationError::Timeout` if the command was not executed in time
+    pub fn wait_timeout(self, timeout: Duration) -> Result<T, NotificationError> {
+        self.receiver.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => NotificationError::Timeout,
+            RecvTimeoutError::Disconnected => NotificationError::Disconnected,
+        })
+    }
+
+    /// Returns the result if the command was already executed
+    pub fn try_get(&self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::MockBackend;
+    use crate::{Notification, NotificationError, NotificationProvider};
+    use std::time::Duration;
+
+    #[test]
+    fn replies_once_the_commands_were_processed() {
+        let mock = MockBackend::new();
+        let provider = NotificationProvider::with_backend(mock.clone());
+        let handle = provider.handle();
+        let reply = handle.send(Notification::new().title("Build finished"));
+        assert!(reply.try_get().is_none());
+        assert!(mock.sent().is_empty());
+
+        provider.process_commands();
+        let id = reply.try_get().unwrap().unwrap();
+        assert_eq!(mock.sent()[0].get_identifier(), id);
+        assert!(reply.try_get().is_none());
+    }
+
+    #[test]
+    fn waiting_times_out_without_processing() {
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let reply = provider.handle().delete_all();
+        assert!(matches!(
+            reply.wait_timeout(Duration::from_millis(50)),
+            Err(NotificationError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn dropping_the_provider_disconnects_pending_replies() {
+        let provider = NotificationProvider::with_backend(MockBackend::new());
+        let handle = provider.handle();
+        let pending = handle.get_all_notifications();
+        let timed = handle.delete_all();
+        drop(provider);
+
+        assert!(matches!(
+            pending.wait(),
+            Err(NotificationError::Disconnected)
+        ));
+        assert!(matches!(
+            timed.wait_timeout(Duration::from_secs(5)),
+            Err(NotificationError::Disconnected)
+        ));
+        assert!(matches!(
+            handle.send(Notification::new()).wait(),
+            Err(NotificationError::Disconnected)
+        ));
+    }
+}
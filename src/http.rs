@@ -0,0 +1,658 @@
+//! # HTTP
+//! A local HTTP server which accepts messages in the publishing format of [ntfy](https://docs.ntfy.sh/publish/),
+//! so scripts and services which already publish to ntfy can send notifications through this crate.
+//!
+//! Messages are published to a topic in one of the ways ntfy supports:
+//! - `POST` or `PUT /<topic>` with the message as body and the other fields as headers, e.g. `X-Title` or `Title`
+//! - `POST` or `PUT /<topic>/publish` (or `/send`, `/trigger`) with the fields as query parameters
+//! - `POST /` with a JSON object, e.g. `{"topic":"ci","title":"Build failed","tags":["warning"],"priority":4}`
+//!
+//! The fields are mapped onto the notification like this:
+//! - the title defaults to the topic, tags which are known emoji short codes are put in front of it
+//! - the message becomes the body, the remaining tags the subtitle
+//! - priority `high` (4) and `max` (5) play the default sound
+//! - `icon` becomes the image
+//! - `click` and `view` actions open their URL, `http` and `broadcast` actions are ignored
+//!
+//! Only `http`, `https` and `mailto` URLs are accepted. Requests with an `Origin` header are rejected,
+//! so websites open in a browser can not post to the server. Publishing with `GET` is not supported,
+//! browsers send it without an `Origin` header, e.g. for an image on a website.
+//!
+//! The answer is a JSON object like ntfy's, its `id` is the identifier of the notification.
+
+use crate::opener::{self, open_url, UrlOpener};
+use crate::server::{read_body, Failure, Handler, JsonServer};
+use crate::{Notification, NotificationHandle, NotificationResponse};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+use tiny_http::{Method, Request};
+
+/// Requests with a bigger body are rejected
+const MAX_BODY: usize = 64 * 1024;
+
+/// Emoji short codes ntfy turns into emojis in front of the title, the most common ones
+const EMOJIS: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("bell", "🔔"),
+    ("check", "✔️"),
+    ("heavy_check_mark", "✔️"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("warning", "⚠️"),
+    ("rotating_light", "🚨"),
+    ("no_entry", "⛔"),
+    ("skull", "💀"),
+    ("tada", "🎉"),
+    ("partying_face", "🥳"),
+    ("loudspeaker", "📢"),
+    ("fire", "🔥"),
+    ("rocket", "🚀"),
+    ("computer", "💻"),
+    ("hourglass", "⌛"),
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct ViewAction {
+    action: String,
+    label: String,
+    url: Option<String>,
+}
+
+/// A message in the JSON format of ntfy, the header format is converted to it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Message {
+    topic: String,
+    message: Option<String>,
+    title: Option<String>,
+    tags: Vec<String>,
+    #[serde(deserialize_with = "deserialize_priority")]
+    priority: Option<u8>,
+    click: Option<String>,
+    icon: Option<String>,
+    actions: Vec<ViewAction>,
+}
+
+fn deserialize_priority<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Priority {
+        Number(u8),
+        Name(String),
+    }
+    match Option::<Priority>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Priority::Number(priority)) => (1..=5)
+            .contains(&priority)
+            .then_some(Some(priority))
+            .ok_or_else(|| serde::de::Error::custom("the priority must be between 1 and 5")),
+        Some(Priority::Name(name)) => parse_priority(&name)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+fn parse_priority(priority: &str) -> Result<u8, String> {
+    match priority.trim().to_ascii_lowercase().as_str() {
+        "1" | "min" => Ok(1),
+        "2" | "low" => Ok(2),
+        "3" | "default" => Ok(3),
+        "4" | "high" => Ok(4),
+        "5" | "max" | "urgent" => Ok(5),
+        _ => Err(format!("invalid priority: {}", priority)),
+    }
+}
+
+/// Parses the short action format of ntfy, e.g. `view, Open, https://example.com; http, Restart, …`.
+/// The fields can also be named, e.g. `action=view, label=Open, url=https://example.com`.
+/// The actions are checked by `Message::validate`
+fn parse_actions(actions: &str) -> Vec<ViewAction> {
+    actions
+        .split(';')
+        .filter(|action| !action.trim().is_empty())
+        .map(|action| {
+            let mut view = ViewAction::default();
+            for (i, part) in action.split(',').map(str::trim).enumerate() {
+                match part.split_once('=') {
+                    Some(("action", value)) => view.action = value.trim().to_string(),
+                    Some(("label", value)) => view.label = value.trim().to_string(),
+                    Some(("url", value)) => view.url = Some(value.trim().to_string()),
+                    _ => match i {
+                        0 => view.action = part.to_string(),
+                        1 => view.label = part.to_string(),
+                        2 => view.url = Some(part.to_string()),
+                        _ => {}
+                    },
+                }
+            }
+            view
+        })
+        .collect()
+}
+
+/// Decodes a component of a query string
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let byte = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+impl Message {
+    /// Builds the message from the headers and query parameters of a request, the body is the message text
+    fn from_fields(
+        topic: &str,
+        fields: &HashMap<String, String>,
+        body: String,
+    ) -> Result<Self, String> {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| fields.get(*name))
+                .map(|value| value.to_string())
+        };
+        let message = field(&["x-message", "message", "m"]).or((!body.is_empty()).then_some(body));
+        Ok(Self {
+            topic: topic.to_string(),
+            message,
+            title: field(&["x-title", "title", "ti", "t"]),
+            tags: field(&["x-tags", "tags", "tag", "ta"])
+                .map(|tags| {
+                    tags.split(',')
+                        .map(|tag| tag.trim().to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            priority: field(&["x-priority", "priority", "prio", "p"])
+                .map(|priority| parse_priority(&priority))
+                .transpose()?,
+            click: field(&["x-click", "click"]),
+            icon: field(&["x-icon", "icon"]),
+            actions: field(&["x-actions", "actions", "action"])
+                .map(|actions| parse_actions(&actions))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The notification and the URLs to open for its responses
+    fn to_notification(&self) -> (Notification, HashMap<String, String>) {
+        let emoji = |tag: &str| {
+            EMOJIS
+                .iter()
+                .find(|(code, _)| *code == tag)
+                .map(|(_, e)| *e)
+        };
+        let emojis: Vec<&str> = self.tags.iter().filter_map(|tag| emoji(tag)).collect();
+        let tags: Vec<&str> = self
+            .tags
+            .iter()
+            .map(String::as_str)
+            .filter(|tag| emoji(tag).is_none())
+            .collect();
+        let title = self.title.as_deref().unwrap_or(&self.topic);
+        let title = match emojis.is_empty() {
+            true => title.to_string(),
+            false => format!("{} {}", emojis.join(" "), title),
+        };
+        let mut notification = Notification::new()
+            .title(&title)
+            .body(self.message.as_deref().unwrap_or("triggered"));
+        if !tags.is_empty() {
+            notification = notification.subtitle(&tags.join(", "));
+        }
+        if self.priority.is_some_and(|priority| priority >= 4) {
+            notification = notification.sound("default");
+        }
+        if let Some(icon) = self.icon.as_deref() {
+            notification = notification.image(icon);
+        }
+
+        let mut urls = HashMap::new();
+        if let Some(click) = self.click.as_deref() {
+            urls.insert(String::new(), click.to_string());
+        }
+        let views = self
+            .actions
+            .iter()
+            .filter(|action| action.action.eq_ignore_ascii_case("view"))
+            .filter_map(|action| Some((action.label.as_str(), action.url.as_deref()?)));
+        for (i, (label, url)) in views.enumerate() {
+            let identifier = format!("view-{}", i);
+            notification = match i {
+                0 => notification.action(&identifier, label),
+                _ => notification.alternate_action(&identifier, label),
+            };
+            urls.insert(identifier, url.to_string());
+        }
+        (notification, urls)
+    }
+
+    /// Rejects what ntfy would reject and URLs which must not be opened
+    fn validate(&self) -> Result<(), String> {
+        if !is_topic(&self.topic) {
+            return Err(format!("invalid topic: {}", self.topic));
+        }
+        let check_url = |url: &str| match opener::is_allowed(url) {
+            true => Ok(()),
+            false => Err(format!("unsupported URL: {}", url)),
+        };
+        if let Some(click) = self.click.as_deref() {
+            check_url(click)?;
+        }
+        if self.actions.len() > 3 {
+            return Err("at most 3 actions are allowed".to_string());
+        }
+        for action in self.actions.iter() {
+            let kind = action.action.to_ascii_lowercase();
+            if !["view", "http", "broadcast"].contains(&kind.as_str()) {
+                return Err(format!("invalid action: {}", action.action));
+            }
+            if action.label.is_empty() {
+                return Err(format!("the {} action has no label", kind));
+            }
+            match (kind.as_str(), action.url.as_deref()) {
+                ("view", Some(url)) => check_url(url)?,
+                ("view" | "http", None) => return Err(format!("the {} action has no URL", kind)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_topic(topic: &str) -> bool {
+    (1..=64).contains(&topic.len())
+        && topic
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Serves the ntfy publishing API on a local port and sends the messages through a `NotificationProvider`
+/// # Example
+/// ```rust
+/// use mac_notifications::backend::MockBackend;
+/// use mac_notifications::http::HttpServer;
+/// use mac_notifications::NotificationProvider;
+/// use std::io::{Read, Write};
+/// use std::net::{SocketAddr, TcpStream};
+/// use std::sync::{Arc, Mutex};
+/// use std::thread;
+///
+/// fn post(address: SocketAddr, path: &str, headers: &str, body: &str) -> String {
+///     let mut stream = TcpStream::connect(address).unwrap();
+///     write!(
+///         stream,
+///         "POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+///         path,
+///         headers,
+///         body.len(),
+///         body
+///     )
+///     .unwrap();
+///     let mut response = String::new();
+///     stream.read_to_string(&mut response).unwrap();
+///     response
+/// }
+///
+/// let mock = MockBackend::new();
+/// let provider = NotificationProvider::with_backend(mock.clone());
+/// let opened = Arc::new(Mutex::new(vec![]));
+/// let urls = opened.clone();
+/// let server = HttpServer::bind("127.0.0.1:0", provider.handle())
+///     .unwrap()
+///     .url_opener(move |url| urls.lock().unwrap().push(url.to_string()));
+/// let address = server.local_addr().unwrap();
+/// server.spawn();
+///
+/// let client = thread::spawn(move || {
+///     let headers = "Title: Deploy failed\r\nPriority: high\r\nTags: warning,production\r\n\
+///                    Click: https://ci.example.com/1\r\n";
+///     let first = post(address, "/deploys", headers, "Deployment of web-1 failed");
+///     let json = r#"{"topic":"backups","message":"Backup done",
+///         "actions":[{"action":"view","label":"Show log","url":"https://backup.example.com/log"}]}"#;
+///     let second = post(address, "/", "", json);
+///     let invalid = post(address, "/deploys", "Priority: very high\r\n", "");
+///     (first, second, invalid)
+/// });
+/// while !client.is_finished() {
+///     provider.run_main_loop_once();
+/// }
+/// let (first, second, invalid) = client.join().unwrap();
+/// assert!(first.starts_with("HTTP/1.1 200") && second.starts_with("HTTP/1.1 200"));
+/// assert!(invalid.starts_with("HTTP/1.1 400"));
+///
+/// let deploy = &mock.sent()[0];
+/// assert!(first.contains(deploy.get_identifier()));
+/// assert_eq!(deploy.get_title(), Some("⚠️ Deploy failed"));
+/// assert_eq!(deploy.get_subtitle(), Some("production"));
+/// assert_eq!(deploy.get_body(), Some("Deployment of web-1 failed"));
+/// assert_eq!(deploy.get_sound(), Some("default"));
+///
+/// let backup = &mock.sent()[1];
+/// assert_eq!(backup.get_title(), Some("backups"));
+/// assert_eq!(backup.get_action().unwrap().title, "Show log");
+///
+/// // clicks and view actions open their URL
+/// mock.click(deploy.get_identifier());
+/// mock.press_action(backup.get_identifier(), &backup.get_action().unwrap().identifier);
+/// assert_eq!(
+///     *opened.lock().unwrap(),
+///     vec!["https://ci.example.com/1", "https://backup.example.com/log"]
+/// );
+/// ```
+pub struct HttpServer {
+    server: JsonServer<Publisher>,
+}
+
+impl HttpServer {
+    /// Listens on the address, e.g. "127.0.0.1:80" so ntfy clients can keep `http://localhost` as their server.
+    /// There is no authentication, the server should only listen on localhost
+    pub fn bind<A: ToSocketAddrs>(address: A, handle: NotificationHandle) -> io::Result<Self> {
+        let publisher = Publisher {
+            handle,
+            opener: Arc::new(open_url),
+        };
+        Ok(Self {
+            server: JsonServer::bind(address, publisher)?,
+        })
+    }
+
+    /// Replaces how URLs of clicked notifications and `view` actions are opened
+    pub fn url_opener<F>(mut self, opener: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.server.handler.opener = Arc::new(opener);
+        self
+    }
+
+    /// The address ntfy clients publish to, with the actual port if the server was bound to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.local_addr()
+    }
+
+    /// Publishes the messages of the requests until the server fails.
+    /// A request is answered once the provider sent its notification
+    pub fn run(&self) -> io::Result<()> {
+        self.server.run()
+    }
+
+    /// Runs `run` on a new thread, so the thread of the provider can run its main loop meanwhile
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        self.server.spawn()
+    }
+}
+
+/// Turns the requests of an `HttpServer` into notifications
+struct Publisher {
+    handle: NotificationHandle,
+    opener: Arc<UrlOpener>,
+}
+
+impl Handler for Publisher {
+    fn respond(&self, request: &mut Request) -> (u16, serde_json::Value) {
+        match self.publish(request) {
+            Ok(body) => (200, body),
+            Err((status, error)) => (
+                status,
+                serde_json::json!({ "code": status * 100, "http": status, "error": error }),
+            ),
+        }
+    }
+}
+
+impl Publisher {
+    fn publish(&self, request: &mut Request) -> Result<serde_json::Value, Failure> {
+        // browsers send it with every cross-origin POST, ntfy clients and scripts never do
+        if request
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("Origin"))
+        {
+            return Err((403, "requests from websites are not allowed".to_string()));
+        }
+        let body = read_body(request, MAX_BODY)?;
+        let body =
+            String::from_utf8(body).map_err(|_| (400, "invalid body: not UTF-8".to_string()))?;
+
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let method = request.method().clone();
+        let message = match (&method, segments.as_slice()) {
+            (Method::Post, []) => serde_json::from_str::<Message>(&body)
+                .map_err(|err| (400, format!("invalid JSON message: {}", err)))?,
+            (Method::Post | Method::Put, [topic])
+            | (Method::Post | Method::Put, [topic, "publish" | "send" | "trigger"]) => {
+                let mut fields: HashMap<String, String> = request
+                    .headers()
+                    .iter()
+                    .map(|header| {
+                        (
+                            header.field.as_str().as_str().to_ascii_lowercase(),
+                            header.value.as_str().to_string(),
+                        )
+                    })
+                    .collect();
+                // query parameters take precedence over headers
+                fields.extend(query.split('&').filter_map(|parameter| {
+                    let (name, value) = parameter.split_once('=')?;
+                    Some((decode(name).to_ascii_lowercase(), decode(value)))
+                }));
+                Message::from_fields(topic, &fields, body).map_err(|err| (400, err))?
+            }
+            (Method::Post | Method::Put, _) => return Err((404, "not found".to_string())),
+            _ => return Err((405, "method not allowed".to_string())),
+        };
+        message.validate().map_err(|err| (400, err))?;
+
+        let (notification, urls) = message.to_notification();
+        let opener = self.opener.clone();
+        let notification = notification.on_response(move |response| {
+            let url = match response {
+                NotificationResponse::Click => urls.get(""),
+                NotificationResponse::ActionButton(action) => urls.get(&action),
+                _ => None,
+            };
+            if let Some(url) = url {
+                opener(url);
+            }
+        });
+        let identifier = self
+            .handle
+            .send(notification)
+            .wait()
+            .and_then(|result| result)
+            .map_err(|err| (500, err.to_string()))?;
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Ok(serde_json::json!({
+            "id": identifier,
+            "time": time,
+            "event": "message",
+            "topic": message.topic,
+            "title": message.title,
+            "message": message.message,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::NotificationProvider;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    fn message(actions: &str) -> Message {
+        Message {
+            topic: "ci".to_string(),
+            actions: parse_actions(actions),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn positional_and_named_actions() {
+        let actions = parse_actions(
+            "view, Open, https://example.com; action=http, url=https://example.com/restart, label=Restart",
+        );
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].action, "view");
+        assert_eq!(actions[0].label, "Open");
+        assert_eq!(actions[0].url.as_deref(), Some("https://example.com"));
+        assert_eq!(actions[1].action, "http");
+        assert_eq!(actions[1].label, "Restart");
+        assert_eq!(
+            actions[1].url.as_deref(),
+            Some("https://example.com/restart")
+        );
+        assert!(message("view, Open, https://example.com; broadcast, Sync")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_actions_are_rejected() {
+        let cases = [
+            ("open, Open, https://example.com", "invalid action: open"),
+            (
+                "view, , https://example.com",
+                "the view action has no label",
+            ),
+            ("view", "the view action has no label"),
+            ("view, Open", "the view action has no URL"),
+            ("http, Restart", "the http action has no URL"),
+            (
+                "view, Open, file:///etc/passwd",
+                "unsupported URL: file:///etc/passwd",
+            ),
+            (
+                "view, Open, x-apple.systempreferences:",
+                "unsupported URL: x-apple.systempreferences:",
+            ),
+            (
+                "broadcast, A; broadcast, B; broadcast, C; broadcast, D",
+                "at most 3 actions are allowed",
+            ),
+        ];
+        for (actions, error) in cases {
+            assert_eq!(
+                message(actions).validate(),
+                Err(error.to_string()),
+                "{}",
+                actions
+            );
+        }
+    }
+
+    #[test]
+    fn click_urls_are_checked() {
+        let mut message = message("");
+        for url in [
+            "https://example.com",
+            "HTTP://example.com",
+            "mailto:ops@example.com",
+        ] {
+            message.click = Some(url.to_string());
+            assert_eq!(message.validate(), Ok(()));
+        }
+        for url in [
+            "javascript:alert(1)",
+            "/Applications/Calculator.app",
+            "ssh://host",
+        ] {
+            message.click = Some(url.to_string());
+            assert!(message.validate().is_err(), "{}", url);
+        }
+    }
+
+    /// Sends a raw request to a server on another thread and returns the status code
+    fn status(request: Vec<u8>) -> u16 {
+        let mock = MockBackend::new();
+        let provider = NotificationProvider::with_backend(mock.clone());
+        let server = HttpServer::bind("127.0.0.1:0", provider.handle()).unwrap();
+        let address = server.local_addr().unwrap();
+        server.spawn();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(&request).unwrap();
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response);
+            response[9..12].parse().unwrap()
+        });
+        while !client.is_finished() {
+            provider.process_commands();
+        }
+        let status = client.join().unwrap();
+        if status != 200 {
+            assert!(mock.sent().is_empty());
+        }
+        status
+    }
+
+    fn post(headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut request = format!(
+            "POST /ci HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n",
+            headers,
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        request
+    }
+
+    #[test]
+    fn requests_from_websites_are_rejected() {
+        assert_eq!(status(post("", b"Build failed")), 200);
+        assert_eq!(
+            status(post(
+                "Origin: https://evil.example.com\r\n",
+                b"Build failed"
+            )),
+            403
+        );
+        // what an image on a website would send
+        let get = b"GET /ci/publish?message=Build%20failed HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+        assert_eq!(status(get.to_vec()), 405);
+    }
+
+    #[test]
+    fn oversized_bodies_are_rejected() {
+        assert_eq!(status(post("", &vec![b'a'; MAX_BODY])), 200);
+        assert_eq!(status(post("", &vec![b'a'; MAX_BODY + 1])), 413);
+    }
+}
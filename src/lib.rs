@@ -1,4 +1,3 @@
-#![cfg(target_os = "macos")]
 #![allow(deprecated)]
 
 //! # macOS Notification Provider
@@ -11,9 +10,19 @@
 //! ## Working principle
 //! The crate provides a `NotificationProvider` struct which is the main struct for interacting with the notifications.
 //! Notifications can be sent directly via the `Notification` struct.
+//! Other threads work with the provider through a `NotificationHandle`, which queues its commands for the thread of the provider.
+//!
+//! Everything OS specific is hidden behind the `NotificationBackend` trait (see the `backend` module).
+//! `NotificationProvider::new` uses the NSUserNotification backend, `NotificationProvider::with_backend` accepts any other implementation.
+//!
+//! ## Platforms
+//! `Notification`, `NotificationResponse`, `NotificationError` and the `NotificationBackend` trait are available on every platform.
+//! The NSUserNotification backend, `NotificationProvider::new` and the `misc` module only exist on macOS,
+//! elsewhere a provider is created with `NotificationProvider::with_backend`.
 //!
 //! ## Example
 //! ```rust
+//! # #[cfg(target_os = "macos")] {
 //! use mac_notifications::{Notification, NotificationProvider};
 //!
 //! let mut provider = NotificationProvider::new("Terminal");
@@ -27,6 +36,7 @@
 //! Notification::new()
 //!     .title("Hello")
 //!     .subtitle("This is a notification")
+//!     .body("With some more details")
 //!     .reply(true)
 //!     .send().unwrap();
 //!
@@ -34,14 +44,54 @@
 //! for _ in 0..50 {
 //!     provider.run_main_loop_once();
 //! }
+//! # }
 //!```
 
+#[cfg(feature = "alertmanager")]
+pub mod alertmanager;
+pub mod backend;
+mod bundle;
+#[cfg(all(feature = "daemon", unix))]
+pub mod daemon;
+#[cfg(target_os = "macos")]
 mod delegate;
+mod dispatcher;
+mod event;
+#[cfg(feature = "gntp")]
+pub mod gntp;
+mod handle;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(target_os = "macos")]
 pub mod misc;
 mod notification;
 mod notification_response;
+#[cfg(any(feature = "http", feature = "gntp"))]
+mod opener;
 mod provider;
+mod recurrence;
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
+#[cfg(any(feature = "http", feature = "alertmanager"))]
+mod server;
+#[cfg(feature = "store")]
+pub mod store;
+#[cfg(feature = "async")]
+pub mod stream;
+mod template;
 
-pub use notification::Notification;
+pub use backend::NotificationBackend;
+pub use bundle::{
+    AppBundle, AppBundleResolver, BundleResolution, BundleSource, FALLBACK_BUNDLE_IDENTIFIER,
+};
+pub use event::NotificationEvent;
+pub use handle::{NotificationHandle, Reply};
+pub use notification::{Notification, NotificationAction, NotificationError};
 pub use notification_response::NotificationResponse;
 pub use provider::NotificationProvider;
+#[cfg(target_os = "macos")]
+pub use provider::NotificationProviderBuilder;
+pub use recurrence::{Recurrence, Weekday};
+#[cfg(feature = "async")]
+pub use stream::{ResponseFuture, ResponseStream};
+pub use template::{NotificationTemplate, TemplateError, Variables};
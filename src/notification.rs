@@ -1,14 +1,162 @@
+#[cfg(target_os = "macos")]
+use crate::backend::MacOSBackend;
+#[cfg(target_os = "macos")]
+use crate::backend::NotificationBackend;
+use crate::provider;
+use crate::recurrence::Recurrence;
+#[cfg(target_os = "macos")]
+use crate::recurrence::Weekday;
+use crate::NotificationResponse;
+#[cfg(target_os = "macos")]
 use objc2::rc::Id;
+#[cfg(target_os = "macos")]
+use objc2::runtime::AnyObject;
+#[cfg(target_os = "macos")]
 use objc2::{msg_send, ClassType};
+#[cfg(target_os = "macos")]
 use objc2_app_kit::NSImage;
+#[cfg(target_os = "macos")]
 use objc2_foundation::{
-    MainThreadMarker, NSDate, NSError, NSString, NSUserNotification, NSUserNotificationCenter,
-    NSURL,
+    NSArray, NSBundle, NSDate, NSDateComponentUndefined, NSDateComponents, NSDictionary, NSError,
+    NSString, NSUserNotification, NSUserNotificationAction, NSURL,
 };
 use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+#[cfg(target_os = "macos")]
+use std::time::Duration;
 use std::time::SystemTime;
 use uuid::Uuid;
 
+/// Key in the `userInfo` of a NSUserNotification holding the identifier of the action button
+#[cfg(target_os = "macos")]
+const ACTION_IDENTIFIER_KEY: &str = "mac_notifications.action_identifier";
+/// Key in the `userInfo` of a NSUserNotification holding the custom title of the close button,
+/// `otherButtonTitle` returns the default title for notifications without one
+#[cfg(target_os = "macos")]
+const CLOSE_BUTTON_KEY: &str = "mac_notifications.close_button";
+/// Key in the `userInfo` of a NSUserNotification holding the image path or URL, NSImage does not keep it
+#[cfg(target_os = "macos")]
+const IMAGE_KEY: &str = "mac_notifications.image";
+/// Key in the `userInfo` of a NSUserNotification holding the encoded `Recurrence`
+#[cfg(target_os = "macos")]
+const RECURRENCE_KEY: &str = "mac_notifications.recurrence";
+/// Weekly notifications are split into one NSUserNotification per weekday,
+/// their identifiers end with this separator and the number of the weekday
+#[cfg(target_os = "macos")]
+const WEEKDAY_SEPARATOR: &str = "#weekday-";
+
+/// Button of a notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NotificationAction {
+    /// Stable identifier reported in `NotificationResponse::ActionButton`
+    pub identifier: String,
+    /// Text shown to the user
+    pub title: String,
+}
+
+impl NotificationAction {
+    pub fn new(identifier: &str, title: &str) -> Self {
+        Self {
+            identifier: identifier.to_string(),
+            title: title.to_string(),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl From<&NSUserNotificationAction> for NotificationAction {
+    fn from(action: &NSUserNotificationAction) -> Self {
+        unsafe {
+            Self {
+                identifier: action
+                    .identifier()
+                    .map(|s| s.to_string())
+                    .unwrap_or_default(),
+                title: action.title().map(|s| s.to_string()).unwrap_or_default(),
+            }
+        }
+    }
+}
+
+/// Returns the identifier of the action button stored in the `userInfo` of the notification
+#[cfg(target_os = "macos")]
+pub(crate) fn action_identifier(notification: &NSUserNotification) -> Option<String> {
+    user_info_string(notification, ACTION_IDENTIFIER_KEY)
+}
+
+/// Returns the identifier of the `Notification` the NSUserNotification was created from
+#[cfg(target_os = "macos")]
+pub(crate) fn notification_identifier(notification: &NSUserNotification) -> Option<String> {
+    let identifier = unsafe { notification.identifier() }?.to_string();
+    match identifier.rsplit_once(WEEKDAY_SEPARATOR) {
+        Some((base, day)) if day.parse().ok().and_then(Weekday::from_number).is_some() => {
+            Some(base.to_string())
+        }
+        _ => Some(identifier),
+    }
+}
+
+/// Reads the recurrence of a notification which was not sent by this crate
+#[cfg(target_os = "macos")]
+fn repeat_interval(notification: &NSUserNotification) -> Option<Recurrence> {
+    unsafe {
+        let interval = notification.deliveryRepeatInterval()?;
+        let value =
+            |value: isize| (value != NSDateComponentUndefined && value > 0).then_some(value as u32);
+        if let Some(minutes) = value(interval.minute()) {
+            Some(Recurrence::Minutes(minutes))
+        } else if let Some(hours) = value(interval.hour()) {
+            Some(Recurrence::Hours(hours))
+        } else if let Some(days) = value(interval.day()) {
+            Some(Recurrence::Days(days))
+        } else if value(interval.weekOfYear()).is_some() {
+            let date = notification
+                .deliveryDate()
+                .map(|date| {
+                    SystemTime::UNIX_EPOCH + Duration::from_secs_f64(date.timeIntervalSince1970())
+                })
+                .unwrap_or_else(SystemTime::now);
+            Some(Recurrence::Weekly(vec![Weekday::of(date)]))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the string stored under `key` in the `userInfo` of the notification
+#[cfg(target_os = "macos")]
+fn user_info_string(notification: &NSUserNotification, key: &str) -> Option<String> {
+    unsafe {
+        let user_info = notification.userInfo()?;
+        let key = NSString::from_str(key);
+        let value = user_info.objectForKey(&key)?;
+        let is_string: bool = msg_send![&*value, isKindOfClass: NSString::class()];
+        if !is_string {
+            return None;
+        }
+        let value: Id<NSString> = Id::cast(value);
+        Some(value.to_string())
+    }
+}
+
+/// Closure attached to a single notification with `Notification::on_response`
+#[derive(Clone)]
+pub(crate) struct ResponseHandler(Arc<dyn Fn(NotificationResponse) + Send + Sync>);
+
+impl ResponseHandler {
+    pub(crate) fn call(&self, response: NotificationResponse) {
+        (self.0)(response)
+    }
+}
+
+impl Debug for ResponseHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ResponseHandler")
+    }
+}
+
 /// # Notification Struct
 ///
 /// ```rust no_run
@@ -19,44 +167,139 @@ use uuid::Uuid;
 /// let notification = Notification::new()
 ///     .title("Hello")
 ///     .subtitle("This is a notification")
+///     .body("With some more details")
 ///     .reply(true)
 ///     .send().unwrap();
 /// ```
+/// # Serialization
+/// With the `serde` feature notifications can be serialized, the delivery date as RFC 3339 string.
+/// Missing fields get their default value and a missing identifier a new one, handlers are not serialized
+/// ```rust
+/// # #[cfg(feature = "serde")] {
+/// use mac_notifications::{Notification, Recurrence};
+///
+/// let notification: Notification = serde_json::from_str(
+///     r#"{
+///         "title": "Backup",
+///         "delivery_date": "2030-01-01T09:00:00Z",
+///         "recurrence": { "days": 1 }
+///     }"#,
+/// )
+/// .unwrap();
+/// assert_eq!(notification.get_title(), Some("Backup"));
+/// assert_eq!(notification.get_recurrence(), Some(&Recurrence::Days(1)));
+/// assert!(!notification.get_identifier().is_empty());
+///
+/// let json = serde_json::to_value(&notification).unwrap();
+/// assert_eq!(json["delivery_date"], "2030-01-01T09:00:00Z");
+/// # }
+/// ```
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Notification {
     /// The unique identifier for the notification.
+    #[cfg_attr(feature = "serde", serde(default = "new_identifier"))]
     identifier: String,
     /// The localized text that provides the notification’s primary description.
     title: Option<String>,
     /// The localized text that provides the notification’s secondary description.
     subtitle: Option<String>,
+    /// The body text of the notification.
+    body: Option<String>,
     /// launches because of the notification.
     image: Option<String>,
     /// The sound that plays when the system delivers the notification.
     sound: Option<String>,
     /// Delivery date
+    #[cfg_attr(feature = "serde", serde(with = "rfc3339"))]
     delivery_date: Option<SystemTime>,
+    /// Repeats the notification after its delivery
+    recurrence: Option<Recurrence>,
     /// Has reply button
     reply: bool,
+    /// The main action button
+    action: Option<NotificationAction>,
+    /// Additional actions shown in the drop down menu of the action button
+    alternate_actions: Vec<NotificationAction>,
+    /// Custom title of the close button
+    close_button: Option<String>,
+    /// Gets called with the response to this notification
+    #[cfg_attr(feature = "serde", serde(skip))]
+    handler: Option<ResponseHandler>,
 }
 
+#[cfg(target_os = "macos")]
 impl From<&NSUserNotification> for Notification {
     fn from(notification: &NSUserNotification) -> Self {
         unsafe {
             Self {
-                identifier: notification.identifier().unwrap().to_string(),
+                identifier: notification_identifier(notification).unwrap(),
                 title: notification.title().map(|s| s.to_string()),
                 subtitle: notification.subtitle().map(|s| s.to_string()),
+                body: notification.informativeText().map(|s| s.to_string()),
                 sound: notification.soundName().map(|s| s.to_string()),
                 reply: notification.hasReplyButton(),
-                delivery_date: None,
-                image: None,
+                delivery_date: notification.deliveryDate().map(|date| {
+                    SystemTime::UNIX_EPOCH + Duration::from_secs_f64(date.timeIntervalSince1970())
+                }),
+                recurrence: user_info_string(notification, RECURRENCE_KEY)
+                    .and_then(|recurrence| Recurrence::decode(&recurrence))
+                    .or_else(|| repeat_interval(notification)),
+                image: user_info_string(notification, IMAGE_KEY),
+                action: match action_identifier(notification) {
+                    Some(identifier) if notification.hasActionButton() => {
+                        Some(NotificationAction {
+                            identifier,
+                            title: notification.actionButtonTitle().to_string(),
+                        })
+                    }
+                    _ => None,
+                },
+                close_button: user_info_string(notification, CLOSE_BUTTON_KEY),
+                handler: None,
+                alternate_actions: match notification.additionalActions() {
+                    Some(actions) => (0..actions.count())
+                        .map(|i| NotificationAction::from(&*actions.objectAtIndex(i)))
+                        .collect(),
+                    None => vec![],
+                },
             }
         }
     }
 }
-impl Into<Id<NSUserNotification>> for Notification {
-    fn into(self) -> Id<NSUserNotification> {
+#[cfg(target_os = "macos")]
+impl Notification {
+    /// Converts the notification for the NSUserNotificationCenter.
+    /// Weekly notifications become one NSUserNotification per weekday, each repeating every week
+    pub(crate) fn to_ns_notifications(
+        &self,
+    ) -> Result<Vec<Id<NSUserNotification>>, NotificationError> {
+        match self.recurrence.as_ref() {
+            Some(Recurrence::Weekly(days)) => {
+                let start = self.delivery_date.unwrap_or_else(SystemTime::now);
+                days.iter()
+                    .map(|day| {
+                        let mut part = self.clone();
+                        part.identifier =
+                            format!("{}{}{}", self.identifier, WEEKDAY_SEPARATOR, day.number());
+                        part.delivery_date =
+                            Some(Recurrence::Weekly(vec![*day]).first_delivery(start));
+                        part.to_ns_notification()
+                    })
+                    .collect()
+            }
+            // without a delivery date the first repetition is due immediately, like on the other backends
+            _ if self.is_scheduled() => Ok(vec![self
+                .clone()
+                .delivery_date(self.first_delivery())
+                .to_ns_notification()?]),
+            _ => Ok(vec![self.to_ns_notification()?]),
+        }
+    }
+
+    /// Converts the notification for the NSUserNotificationCenter
+    pub(crate) fn to_ns_notification(&self) -> Result<Id<NSUserNotification>, NotificationError> {
         unsafe {
             let notification = NSUserNotification::new();
 
@@ -72,6 +315,10 @@ impl Into<Id<NSUserNotification>> for Notification {
                 let ns_str = NSString::from_str(&subtitle);
                 notification.setSubtitle(Some(&ns_str));
             }
+            if let Some(body) = self.body.as_ref() {
+                let ns_str = NSString::from_str(&body);
+                notification.setInformativeText(Some(&ns_str));
+            }
             if let Some(sound) = self.sound.as_ref() {
                 let ns_str = NSString::from_str(&sound);
                 notification.setSoundName(Some(&ns_str));
@@ -79,49 +326,176 @@ impl Into<Id<NSUserNotification>> for Notification {
             if let Some(delivery_date) = self.delivery_date {
                 let timestamp = delivery_date
                     .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                let date = NSDate::dateWithTimeIntervalSince1970(timestamp as f64);
+                    .map_err(|_| NotificationError::InvalidDeliveryDate)?
+                    .as_secs_f64();
+                let date = NSDate::dateWithTimeIntervalSince1970(timestamp);
                 notification.setDeliveryDate(Some(date.as_ref()));
             }
+            if let Some(recurrence) = self.recurrence.as_ref() {
+                let interval = NSDateComponents::new();
+                match recurrence {
+                    Recurrence::Minutes(minutes) => interval.setMinute(*minutes as isize),
+                    Recurrence::Hours(hours) => interval.setHour(*hours as isize),
+                    Recurrence::Days(days) => interval.setDay(*days as isize),
+                    Recurrence::Weekly(_) => interval.setWeekOfYear(1),
+                }
+                notification.setDeliveryRepeatInterval(Some(&interval));
+            }
             if let Some(image) = self.image.as_ref() {
                 let ns_str = NSString::from_str(&image);
-                let ns_url = NSURL::URLWithString(&ns_str).unwrap();
-                let ns_image = NSImage::initWithContentsOfURL(NSImage::alloc(), &ns_url).unwrap();
+                let ns_url = match image_path(image) {
+                    Some(_) => NSURL::fileURLWithPath(&ns_str),
+                    None => NSURL::URLWithString(&ns_str)
+                        .ok_or_else(|| NotificationError::InvalidImage(image.clone()))?,
+                };
+                let ns_image = NSImage::initWithContentsOfURL(NSImage::alloc(), &ns_url)
+                    .ok_or_else(|| NotificationError::ImageLoadFailed(image.clone()))?;
                 let _: () = msg_send![notification.as_ref(), setContentImage:ns_image.as_ref()];
             }
 
             if self.reply {
                 notification.setHasReplyButton(self.reply);
             }
-            notification
+            if let Some(close_button) = self.close_button.as_ref() {
+                let ns_str = NSString::from_str(close_button);
+                notification.setOtherButtonTitle(&ns_str);
+            }
+            if let Some(action) = self.action.as_ref() {
+                notification.setHasActionButton(true);
+                let ns_str = NSString::from_str(&action.title);
+                notification.setActionButtonTitle(&ns_str);
+            }
+            // values NSUserNotification can not return are kept in the userInfo
+            let recurrence = self.recurrence.as_ref().map(|r| r.encode());
+            let user_info: Vec<(&str, &str)> = [
+                (
+                    ACTION_IDENTIFIER_KEY,
+                    self.action.as_ref().map(|a| a.identifier.as_str()),
+                ),
+                (CLOSE_BUTTON_KEY, self.close_button.as_deref()),
+                (IMAGE_KEY, self.image.as_deref()),
+                (RECURRENCE_KEY, recurrence.as_deref()),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect();
+            if !user_info.is_empty() {
+                let keys: Vec<Id<NSString>> = user_info
+                    .iter()
+                    .map(|(key, _)| NSString::from_str(key))
+                    .collect();
+                let keys: Vec<&NSString> = keys.iter().map(|key| &**key).collect();
+                let values: Vec<Id<AnyObject>> = user_info
+                    .iter()
+                    .map(|(_, value)| Id::into_super(Id::into_super(NSString::from_str(value))))
+                    .collect();
+                let user_info = NSDictionary::from_vec(&keys, values);
+                notification.setUserInfo(Some(&user_info));
+            }
+            if !self.alternate_actions.is_empty() {
+                let actions: Vec<Id<NSUserNotificationAction>> = self
+                    .alternate_actions
+                    .iter()
+                    .map(|action| {
+                        let identifier = NSString::from_str(&action.identifier);
+                        let title = NSString::from_str(&action.title);
+                        NSUserNotificationAction::actionWithIdentifier_title(
+                            Some(&identifier),
+                            Some(&title),
+                        )
+                    })
+                    .collect();
+                notification.setAdditionalActions(Some(&NSArray::from_vec(actions)));
+            }
+            Ok(notification)
         }
     }
 }
 
 impl Notification {
+    /// Sends the notification through the backend of the current `NotificationProvider`.
+    /// Notifications with a delivery date or a recurrence get scheduled instead of delivered immediately.
+    ///
+    /// Without a provider the NSUserNotification backend is used on macOS,
+    /// on other platforms `NotificationError::NotSupported` is returned.
+    /// Invalid images and delivery dates are reported as errors, see `validate`
+    /// # Panics
+    /// Panics if no provider exists and the function is not called on the main thread
     pub fn send(self) -> Result<String, NotificationError> {
-        MainThreadMarker::new().expect("send() must be on the main thread");
-        let identifier = self.identifier.clone();
-        let delivery_date = self.delivery_date.clone();
-        unsafe {
-            let notification: Id<NSUserNotification> = self.into();
-            let notification_center = NSUserNotificationCenter::defaultUserNotificationCenter();
-            match delivery_date {
-                Some(_) => notification_center.scheduleNotification(notification.as_ref()),
-                None => notification_center.deliverNotification(notification.as_ref()),
+        match provider::current() {
+            Some(context) => context.send(self),
+            #[cfg(target_os = "macos")]
+            None => {
+                self.validate()?;
+                // without the bundle hook of a provider unbundled binaries have no identifier
+                let bundle = NSBundle::mainBundle();
+                if unsafe { bundle.bundleIdentifier() }.is_none() {
+                    return Err(NotificationError::BundleNotResolved);
+                }
+                let backend = MacOSBackend::new();
+                if self.is_scheduled() {
+                    backend.schedule(&self)?;
+                } else {
+                    backend.deliver(&self)?;
+                }
+                Ok(self.identifier)
             }
+            #[cfg(not(target_os = "macos"))]
+            None => Err(NotificationError::NotSupported),
         }
-        Ok(identifier)
     }
 }
+fn new_identifier() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Delivery dates as RFC 3339 strings in UTC
+#[cfg(feature = "serde")]
+mod rfc3339 {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::SystemTime;
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => serializer.serialize_some(
+                &DateTime::<Utc>::from(*date).to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            ),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|date| {
+                DateTime::parse_from_rfc3339(&date)
+                    .map(SystemTime::from)
+                    .map_err(serde::de::Error::custom)
+            })
+            .transpose()
+    }
+}
+
 impl Notification {
     pub fn new() -> Self {
         Self {
-            identifier: Uuid::new_v4().to_string(),
+            identifier: new_identifier(),
             ..Default::default()
         }
     }
+
+    /// Replaces the random identifier, e.g. to derive it from the thing the notification is about.
+    /// On macOS a notification replaces a delivered one with the same identifier
+    pub fn identifier(mut self, identifier: &str) -> Self {
+        self.identifier = identifier.to_string();
+        self
+    }
+
     pub fn title(mut self, title: &str) -> Self {
         self.title = Some(title.to_string());
         self
@@ -132,6 +506,11 @@ impl Notification {
         self
     }
 
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self
+    }
+
     /// This can be either a file path or a URL
     /// base64 encoded images are not supported too
     pub fn image(mut self, image: &str) -> Self {
@@ -152,32 +531,386 @@ impl Notification {
         self.delivery_date = Some(delivery_date);
         self
     }
+
+    /// Repeats the notification, starting at the delivery date or immediately without one.
+    /// macOS keeps repeating it until it is cancelled with `NotificationProvider::cancel_scheduled`
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider, Recurrence, Weekday};
+    ///
+    /// let mock = MockBackend::new();
+    /// let provider = NotificationProvider::with_backend(mock.clone());
+    ///
+    /// let id = Notification::new()
+    ///     .title("Timesheet")
+    ///     .recurrence(Recurrence::Weekly(vec![Weekday::Friday]))
+    ///     .send()
+    ///     .unwrap();
+    ///
+    /// // recurring notifications stay scheduled after their delivery
+    /// mock.deliver_scheduled();
+    /// assert_eq!(provider.get_all_notifications().len(), 1);
+    /// let scheduled = provider.get_scheduled_notifications();
+    /// assert_eq!(scheduled[0].get_identifier(), id);
+    /// assert_eq!(
+    ///     scheduled[0].get_recurrence(),
+    ///     Some(&Recurrence::Weekly(vec![Weekday::Friday]))
+    /// );
+    /// assert_eq!(Weekday::of(scheduled[0].get_delivery_date().unwrap()), Weekday::Friday);
+    /// ```
+    pub fn recurrence(mut self, recurrence: Recurrence) -> Self {
+        self.recurrence = Some(recurrence);
+        self
+    }
+
+    /// Sets the main action button. When the user clicks it the response is
+    /// `NotificationResponse::ActionButton` with the given identifier
+    pub fn action(mut self, identifier: &str, title: &str) -> Self {
+        self.action = Some(NotificationAction::new(identifier, title));
+        self
+    }
+
+    /// Adds an action to the drop down menu of the main action button.
+    /// On macOS the menu only shows up if the main action is set as well
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider, NotificationResponse};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let mock = MockBackend::new();
+    /// let mut provider = NotificationProvider::with_backend(mock.clone());
+    /// let chosen = Rc::new(RefCell::new(None));
+    /// let recorded = chosen.clone();
+    /// provider.set_callback(move |_, resp| *recorded.borrow_mut() = Some(resp));
+    ///
+    /// let id = Notification::new()
+    ///     .title("Disk almost full")
+    ///     .action("ack", "Acknowledge")
+    ///     .alternate_action("snooze", "Snooze 10m")
+    ///     .alternate_action("escalate", "Escalate")
+    ///     .send()
+    ///     .unwrap();
+    ///
+    /// mock.press_action(&id, "snooze");
+    /// assert_eq!(
+    ///     *chosen.borrow(),
+    ///     Some(NotificationResponse::ActionButton("snooze".to_string()))
+    /// );
+    /// ```
+    pub fn alternate_action(mut self, identifier: &str, title: &str) -> Self {
+        self.alternate_actions
+            .push(NotificationAction::new(identifier, title));
+        self
+    }
+
+    /// Sets the title of the close button. Closing the notification with it is reported as
+    /// `NotificationResponse::CloseButton` with this title, other dismissals as `NotificationResponse::Dismissed`
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider, NotificationResponse};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let mock = MockBackend::new();
+    /// let mut provider = NotificationProvider::with_backend(mock.clone());
+    /// let responses = Rc::new(RefCell::new(vec![]));
+    /// let recorded = responses.clone();
+    /// provider.set_callback(move |_, resp| recorded.borrow_mut().push(resp));
+    ///
+    /// let first = Notification::new().title("Build failed").close_button("Ignore").send().unwrap();
+    /// let second = Notification::new().title("Build failed again").send().unwrap();
+    ///
+    /// mock.close(&first);
+    /// mock.dismiss(&second);
+    /// assert_eq!(
+    ///     responses.borrow().as_slice(),
+    ///     &[
+    ///         NotificationResponse::CloseButton("Ignore".to_string()),
+    ///         NotificationResponse::Dismissed
+    ///     ]
+    /// );
+    /// ```
+    pub fn close_button(mut self, title: &str) -> Self {
+        self.close_button = Some(title.to_string());
+        self
+    }
+
+    /// Attaches a closure which gets called with the response to this notification only.
+    /// The handler is registered at the `NotificationProvider` of the current thread when the notification is sent.
+    /// It is removed after the first response, when the notification gets deleted or when it expires.
+    /// Recurring notifications keep it until they are cancelled with `NotificationProvider::cancel_scheduled`
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider, NotificationResponse};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mock = MockBackend::new();
+    /// let provider = NotificationProvider::with_backend(mock.clone());
+    ///
+    /// let acknowledged = Arc::new(Mutex::new(false));
+    /// let flag = acknowledged.clone();
+    /// let alert = Notification::new()
+    ///     .title("Disk almost full")
+    ///     .action("ack", "Acknowledge")
+    ///     .on_response(move |resp| {
+    ///         *flag.lock().unwrap() = resp == NotificationResponse::ActionButton("ack".to_string());
+    ///     })
+    ///     .send()
+    ///     .unwrap();
+    /// let other = Notification::new().title("Build finished").send().unwrap();
+    ///
+    /// mock.click(&other);
+    /// assert!(!*acknowledged.lock().unwrap());
+    /// mock.press_action(&alert, "ack");
+    /// assert!(*acknowledged.lock().unwrap());
+    /// ```
+    pub fn on_response<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(NotificationResponse) + Send + Sync + 'static,
+    {
+        self.handler = Some(ResponseHandler(Arc::new(handler)));
+        self
+    }
 }
+impl Notification {
+    pub fn get_identifier(&self) -> &str {
+        &self.identifier
+    }
 
-#[derive(Clone)]
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn get_subtitle(&self) -> Option<&str> {
+        self.subtitle.as_deref()
+    }
+
+    pub fn get_body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    pub fn get_image(&self) -> Option<&str> {
+        self.image.as_deref()
+    }
+
+    pub fn get_sound(&self) -> Option<&str> {
+        self.sound.as_deref()
+    }
+
+    pub fn get_delivery_date(&self) -> Option<SystemTime> {
+        self.delivery_date
+    }
+
+    pub fn get_recurrence(&self) -> Option<&Recurrence> {
+        self.recurrence.as_ref()
+    }
+
+    pub fn get_reply(&self) -> bool {
+        self.reply
+    }
+
+    pub fn get_action(&self) -> Option<&NotificationAction> {
+        self.action.as_ref()
+    }
+
+    pub fn get_alternate_actions(&self) -> &[NotificationAction] {
+        &self.alternate_actions
+    }
+
+    pub fn get_close_button(&self) -> Option<&str> {
+        self.close_button.as_deref()
+    }
+
+    /// Checks the notification before it is sent, `send` does this automatically.
+    /// Fails if the delivery date lies before 1970, the recurrence has no interval or if the image is empty or
+    /// a file path that does not exist. Remote images are only checked when sending on macOS
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::{Notification, NotificationError};
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let notification = Notification::new().image("/does/not/exist.png");
+    /// assert!(matches!(
+    ///     notification.validate(),
+    ///     Err(NotificationError::ImageLoadFailed(_))
+    /// ));
+    ///
+    /// let notification =
+    ///     Notification::new().delivery_date(SystemTime::UNIX_EPOCH - Duration::from_secs(1));
+    /// let error = notification.validate().unwrap_err();
+    /// assert_eq!(error.to_string(), "the delivery date lies before the unix epoch");
+    /// ```
+    pub fn validate(&self) -> Result<(), NotificationError> {
+        if self
+            .delivery_date
+            .is_some_and(|date| date < SystemTime::UNIX_EPOCH)
+        {
+            return Err(NotificationError::InvalidDeliveryDate);
+        }
+        if self.recurrence.as_ref().is_some_and(|r| !r.is_valid()) {
+            return Err(NotificationError::InvalidRecurrence);
+        }
+        if let Some(image) = self.image.as_ref() {
+            if image.trim().is_empty() {
+                return Err(NotificationError::InvalidImage(image.clone()));
+            }
+            if image_path(image).is_some_and(|path| !Path::new(path).is_file()) {
+                return Err(NotificationError::ImageLoadFailed(image.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// A copy of a template gets its own identifier
+    #[cfg(feature = "scheduler")]
+    pub(crate) fn renew_identifier(mut self) -> Self {
+        self.identifier = Uuid::new_v4().to_string();
+        self
+    }
+
+    /// Whether the notification goes through `NotificationBackend::schedule`
+    pub(crate) fn is_scheduled(&self) -> bool {
+        self.delivery_date.is_some() || self.recurrence.is_some()
+    }
+
+    /// The first delivery date of a scheduled notification
+    pub(crate) fn first_delivery(&self) -> SystemTime {
+        let start = self.delivery_date.unwrap_or_else(SystemTime::now);
+        match self.recurrence.as_ref() {
+            Some(recurrence) => recurrence.first_delivery(start),
+            None => start,
+        }
+    }
+
+    /// The notification with its delivery date moved to the next repetition after `now`,
+    /// `None` if it does not repeat
+    pub(crate) fn next_occurrence(&self, now: SystemTime) -> Option<Self> {
+        let recurrence = self.recurrence.as_ref()?;
+        let mut next = recurrence.next_delivery(self.delivery_date.unwrap_or(now));
+        while next <= now {
+            next = recurrence.next_delivery(next);
+        }
+        Some(self.clone().delivery_date(next))
+    }
+
+    pub(crate) fn handler(&self) -> Option<&ResponseHandler> {
+        self.handler.as_ref()
+    }
+}
+
+/// Returns the image as file path, `None` if it is a URL
+pub(crate) fn image_path(image: &str) -> Option<&str> {
+    let is_url = image.split_once("://").is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    (!is_url).then_some(image)
+}
+
+/// With the `serde` feature errors can be serialized, `NSError` can not be deserialized
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NotificationError {
     /// Error from the Objective C User Notifications framework
-    NSError(Id<NSError>),
+    #[cfg(target_os = "macos")]
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
+    NSError {
+        code: isize,
+        domain: String,
+        message: String,
+    },
     /// Not supported for the current OS version
     NotSupported,
+    /// Error reported by the backend, e.g. the D-Bus connection failed
+    Backend(String),
+    /// The user did not respond in time
+    Timeout,
+    /// The `NotificationProvider` a `NotificationHandle` belongs to was dropped
+    Disconnected,
+    /// No `NotificationProvider` was created on the current thread
+    NoProvider,
+    /// The image is neither a file path nor a valid URL
+    InvalidImage(String),
+    /// The image could not be loaded, e.g. the file does not exist
+    ImageLoadFailed(String),
+    /// The delivery date lies before 1970-01-01
+    InvalidDeliveryDate,
+    /// The application has no bundle identifier, macOS drops its notifications silently.
+    /// Create a `NotificationProvider` first
+    BundleNotResolved,
+    /// No installed application has this name or bundle identifier
+    AppNotFound(String),
+    /// No notification has this identifier
+    NotFound(String),
+    /// The recurrence has an interval of zero or no weekdays
+    InvalidRecurrence,
+    /// The cron expression or time zone of a schedule is invalid
+    InvalidSchedule(String),
+    /// Reading or writing the schedule store failed
+    Store(String),
 }
 
-impl From<Id<NSError>> for NotificationError {
-    fn from(value: Id<NSError>) -> Self {
-        Self::NSError(value)
+impl std::fmt::Display for NotificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(target_os = "macos")]
+            NotificationError::NSError {
+                code,
+                domain,
+                message,
+            } => write!(f, "{} ({} {})", message, domain, code),
+            NotificationError::NotSupported => {
+                f.write_str("notifications are not supported on this platform")
+            }
+            NotificationError::Backend(message) => write!(f, "backend error: {}", message),
+            NotificationError::Timeout => f.write_str("timed out waiting for a response"),
+            NotificationError::Disconnected => f.write_str("the notification provider was dropped"),
+            NotificationError::NoProvider => {
+                f.write_str("no notification provider on the current thread")
+            }
+            NotificationError::InvalidImage(image) => write!(f, "invalid image: {}", image),
+            NotificationError::ImageLoadFailed(image) => {
+                write!(f, "could not load image: {}", image)
+            }
+            NotificationError::InvalidDeliveryDate => {
+                f.write_str("the delivery date lies before the unix epoch")
+            }
+            NotificationError::BundleNotResolved => {
+                f.write_str("the application has no bundle identifier")
+            }
+            NotificationError::AppNotFound(app_name) => {
+                write!(f, "application not found: {}", app_name)
+            }
+            NotificationError::NotFound(identifier) => {
+                write!(f, "notification not found: {}", identifier)
+            }
+            NotificationError::InvalidRecurrence => f.write_str("the recurrence has no interval"),
+            NotificationError::InvalidSchedule(message) => {
+                write!(f, "invalid schedule: {}", message)
+            }
+            NotificationError::Store(message) => write!(f, "schedule store: {}", message),
+        }
     }
 }
 
-impl Debug for NotificationError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            NotificationError::NSError(ns_error) => f
-                .debug_struct("NSError")
-                .field("code", &ns_error.code())
-                .field("domain", &ns_error.domain().to_string())
-                .field("message", &ns_error.localizedDescription().to_string())
-                .finish(),
-            NotificationError::NotSupported => f.write_str("NotSupported"),
+impl std::error::Error for NotificationError {}
+
+#[cfg(target_os = "macos")]
+impl From<Id<NSError>> for NotificationError {
+    fn from(value: Id<NSError>) -> Self {
+        // copied, because NSError can not be sent to other threads
+        Self::NSError {
+            code: value.code(),
+            domain: value.domain().to_string(),
+            message: value.localizedDescription().to_string(),
         }
     }
 }
@@ -1,27 +1,41 @@
+#[cfg(target_os = "macos")]
+use crate::notification::action_identifier;
+#[cfg(target_os = "macos")]
 use objc2_foundation::{NSUserNotification, NSUserNotificationActivationType};
 
 /// Response from the Notification
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NotificationResponse {
-    /// No interaction has occured
+    /// No interaction has occured, e.g. the notification expired
     None,
-    /// User clicked on an action button with the given name
+    /// User clicked on an action button with the given identifier
     ActionButton(String),
-    /// User clicked on the close button with the given name
+    /// User clicked on the close button with the given title
     CloseButton(String),
+    /// User removed the notification without using a button, e.g. by swiping it away
+    Dismissed,
     /// User clicked the notification directly
     Click,
     /// User submitted text to the input text field
     Reply(String),
 }
 
+#[cfg(target_os = "macos")]
 impl NotificationResponse {
     pub(crate) fn from_dictionary(notification: &NSUserNotification) -> Self {
         return unsafe {
             match notification.activationType() {
                 NSUserNotificationActivationType::None => Self::None,
-                NSUserNotificationActivationType::ActionButtonClicked => {
-                    return Self::ActionButton("todo".to_string());
+                NSUserNotificationActivationType::ActionButtonClicked
+                | NSUserNotificationActivationType::AdditionalActionClicked => {
+                    // actions of the drop down menu are reported as additionalActivationAction
+                    let identifier = match notification.additionalActivationAction() {
+                        Some(action) => action.identifier().map(|s| s.to_string()),
+                        None => action_identifier(notification),
+                    };
+                    Self::ActionButton(identifier.unwrap_or_default())
                 }
                 NSUserNotificationActivationType::ContentsClicked => Self::Click,
                 NSUserNotificationActivationType::Replied => {
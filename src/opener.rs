@@ -0,0 +1,31 @@
+use std::process::Command;
+
+/// Called with the URL of a clicked notification, e.g. by `http::HttpServer`
+pub(crate) type UrlOpener = dyn Fn(&str) + Send + Sync;
+
+/// Schemes the servers accept for URLs which are opened on a click.
+/// Anything else could start an arbitrary application registered for its scheme, or open a local file
+const SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Whether the URL may be opened when the notification is clicked
+pub(crate) fn is_allowed(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        SCHEMES
+            .iter()
+            .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+    })
+}
+
+/// Opens the URL with the default application of the OS, URLs which are not `is_allowed` are ignored
+pub(crate) fn open_url(url: &str) {
+    if !is_allowed(url) {
+        return;
+    }
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    // the click is gone already, there is nobody left to report a failure to
+    let _ = Command::new(program).arg(url).spawn();
+}
@@ -1,25 +1,27 @@
-use crate::delegate::RustNotificationDelegate;
-use crate::{Notification, NotificationResponse};
-use objc2::rc::Id;
-use objc2::runtime::NSObjectProtocol;
-use objc2::ClassType;
-use objc2_foundation::{
-    MainThreadMarker, NSDate, NSDefaultRunLoopMode, NSRunLoop, NSString, NSUserNotificationCenter,
-};
-use std::ops::Deref;
-
-mod sys {
-    use objc2_foundation::NSString;
-
-    #[link(name = "notification")]
-    extern "C" {
-        pub fn init(app_name: *const NSString);
-    }
-}
+#[cfg(target_os = "macos")]
+use crate::backend::MacOSBackend;
+use crate::backend::NotificationBackend;
+use crate::bundle::BundleResolution;
+#[cfg(target_os = "macos")]
+use crate::bundle::{self, AppBundleResolver};
+use crate::dispatcher::Dispatcher;
+use crate::handle::{self, Command, NotificationHandle};
+#[cfg(feature = "store")]
+use crate::store::{CatchUp, Reconciliation, ScheduleStore};
+#[cfg(feature = "async")]
+use crate::stream::ResponseStream;
+use crate::{Notification, NotificationError, NotificationEvent, NotificationResponse};
+#[cfg(target_os = "macos")]
+use objc2_foundation::{MainThreadMarker, NSDate, NSDefaultRunLoopMode, NSRunLoop};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::SystemTime;
 
 /// the main struct for the notification provider
 /// # Example
 /// ```rust
+/// # #[cfg(target_os = "macos")] {
 /// use mac_notifications::{Notification, NotificationProvider};
 ///
 /// let mut provider = NotificationProvider::new("Terminal");
@@ -36,46 +38,292 @@ mod sys {
 /// for _ in 0..50 {
 ///     provider.run_main_loop_once();
 /// }
+/// # }
 /// ```
 pub struct NotificationProvider {
-    delegate: Option<Id<RustNotificationDelegate>>,
-    center: Id<NSUserNotificationCenter>,
+    context: Rc<Context>,
+    commands: Receiver<Command>,
+    command_sender: Sender<Command>,
+    bundle: Option<BundleResolution>,
+}
+
+/// The parts of a provider `Notification::send` works with
+pub(crate) struct Context {
+    pub(crate) backend: Rc<dyn NotificationBackend>,
+    pub(crate) dispatcher: Rc<Dispatcher>,
+    #[cfg(feature = "store")]
+    store: Rc<RefCell<Option<ScheduleStore>>>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<Context>>> = const { RefCell::new(None) };
+}
+
+impl Context {
+    pub(crate) fn send(&self, notification: Notification) -> Result<String, NotificationError> {
+        notification.validate()?;
+        let identifier = notification.get_identifier().to_string();
+        // the backend may report a response while sending, so the handler has to be registered first
+        if let Some(handler) = notification.handler() {
+            self.dispatcher.register_handler(
+                &identifier,
+                handler.clone(),
+                notification.get_recurrence().is_some(),
+            );
+        }
+        // recorded before scheduling, the backend may already report the delivery
+        #[cfg(feature = "store")]
+        if notification.is_scheduled() {
+            let stored = notification
+                .clone()
+                .delivery_date(notification.first_delivery());
+            if let Err(err) = self.update_store(|store| store.insert(&stored)) {
+                self.dispatcher.remove_handler(&identifier);
+                return Err(err);
+            }
+        }
+        let result = if notification.is_scheduled() {
+            self.backend.schedule(&notification)
+        } else {
+            self.backend.deliver(&notification)
+        };
+        if result.is_err() {
+            self.dispatcher.remove_handler(&identifier);
+            #[cfg(feature = "store")]
+            let _ = self.update_store(|store| store.remove(&identifier));
+        }
+        result.map(|_| identifier)
+    }
+
+    /// Runs `f` on the attached store, does nothing without one
+    #[cfg(feature = "store")]
+    fn update_store<F>(&self, f: F) -> Result<(), NotificationError>
+    where
+        F: FnOnce(&mut ScheduleStore) -> Result<(), NotificationError>,
+    {
+        update_store(&self.store, f)
+    }
+}
+
+#[cfg(feature = "store")]
+fn update_store<F>(store: &RefCell<Option<ScheduleStore>>, f: F) -> Result<(), NotificationError>
+where
+    F: FnOnce(&mut ScheduleStore) -> Result<(), NotificationError>,
+{
+    match store.borrow_mut().as_mut() {
+        Some(store) => f(store),
+        None => Ok(()),
+    }
+}
+
+/// Returns the context of the most recently created provider on the current thread
+pub(crate) fn current() -> Option<Rc<Context>> {
+    CURRENT.with(|current| current.borrow().clone())
 }
 
 impl NotificationProvider {
-    /// Creates a new NotificationProvider with the name of the application e.g. "Terminal"
+    /// Creates a new NotificationProvider with the name of the application e.g. "Terminal".
+    ///
+    /// If the application is not installed the notifications are silently sent on behalf of the Finder,
+    /// `bundle_resolution().is_fallback()` tells whether that happened. Use
+    /// `builder().app_name(..).fallback(false)` to get `NotificationError::AppNotFound` instead
     /// # Panics
     //  Panics if the callback is not called on the main thread
+    #[cfg(target_os = "macos")]
     pub fn new(app_name: &str) -> Self {
-        MainThreadMarker::new().expect("init() must be on the main thread");
-        let app_name = NSString::from_str(app_name);
-        let app_name = app_name.deref();
+        Self::builder()
+            .app_name(app_name)
+            .build()
+            .expect("could not install the bundle identifier hook")
+    }
 
-        unsafe {
-            sys::init(app_name);
-        };
-        let center = unsafe { NSUserNotificationCenter::defaultUserNotificationCenter() };
+    /// Configures the bundle identifier the notifications are sent with
+    /// # Example
+    /// ```rust
+    /// # #[cfg(target_os = "macos")] {
+    /// use mac_notifications::{BundleSource, NotificationProvider};
+    ///
+    /// let provider = NotificationProvider::builder()
+    ///     .bundle_identifier("com.apple.Terminal")
+    ///     .fallback(false)
+    ///     .build()
+    ///     .unwrap();
+    /// let resolution = provider.bundle_resolution().unwrap();
+    /// assert_eq!(resolution.identifier(), "com.apple.Terminal");
+    /// assert_eq!(resolution.source(), BundleSource::Explicit);
+    /// # }
+    /// ```
+    #[cfg(target_os = "macos")]
+    pub fn builder() -> NotificationProviderBuilder {
+        NotificationProviderBuilder::default()
+    }
+
+    /// The bundle identifier the notifications are sent with,
+    /// `None` if the provider was created with `with_backend`
+    pub fn bundle_resolution(&self) -> Option<&BundleResolution> {
+        self.bundle.as_ref()
+    }
+
+    /// Creates a new NotificationProvider on top of a custom backend.
+    /// From now on `Notification::send` on this thread goes through the given backend
+    pub fn with_backend<B>(backend: B) -> Self
+    where
+        B: NotificationBackend + 'static,
+    {
+        let backend: Rc<dyn NotificationBackend> = Rc::new(backend);
+        let dispatcher = Rc::new(Dispatcher::default());
+        let events = dispatcher.clone();
+        #[cfg(feature = "store")]
+        let store = Rc::new(RefCell::new(None));
+        #[cfg(feature = "store")]
+        let delivered = store.clone();
+        backend.subscribe(Box::new(move |event| {
+            #[cfg(feature = "store")]
+            if let NotificationEvent::Delivered { identifier } = &event {
+                // the store keeps the change in memory, a failed write is repeated with its next change
+                let _ = update_store(&delivered, |store| {
+                    store.delivered(identifier, SystemTime::now())
+                });
+            }
+            events.dispatch(event)
+        }));
+        let context = Rc::new(Context {
+            backend,
+            dispatcher,
+            #[cfg(feature = "store")]
+            store,
+        });
+        CURRENT.with(|current| *current.borrow_mut() = Some(context.clone()));
+        let (command_sender, commands) = mpsc::channel();
         Self {
-            delegate: None,
-            center,
+            context,
+            commands,
+            command_sender,
+            bundle: None,
         }
     }
 
-    /// This callback gets called when a notification was interacted with
+    /// This callback gets called when a notification was interacted with.
+    /// A previously set callback gets replaced, subscribers are not affected
     pub fn set_callback<F>(&mut self, callback: F)
     where
         F: Fn(String, NotificationResponse) + 'static,
     {
-        let delegate = RustNotificationDelegate::new(callback);
-        self.delegate = Some(delegate);
+        self.context.dispatcher.set_callback(Box::new(callback));
+    }
+
+    /// Returns a receiver for the events of all notifications.
+    /// Every call creates a new independent subscriber, dropping the receiver unsubscribes.
+    /// Events arrive while the main loop is running
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationEvent, NotificationProvider};
+    ///
+    /// let mock = MockBackend::new();
+    /// let provider = NotificationProvider::with_backend(mock.clone());
+    /// let alerts = provider.subscribe();
+    /// let audit_log = provider.subscribe();
+    ///
+    /// let id = Notification::new().title("Deploy finished").send().unwrap();
+    /// mock.press_action(&id, "rollback");
+    ///
+    /// let events: Vec<NotificationEvent> = alerts.try_iter().collect();
+    /// assert_eq!(
+    ///     events,
+    ///     vec![
+    ///         NotificationEvent::Delivered { identifier: id.clone() },
+    ///         NotificationEvent::Action { identifier: id.clone(), action: "rollback".to_string() },
+    ///     ]
+    /// );
+    /// assert_eq!(audit_log.try_iter().count(), 2);
+    /// ```
+    pub fn subscribe(&self) -> Receiver<NotificationEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.add_subscriber(sender);
+        receiver
     }
-    /// Runs the main loop for .1 seconds
+
+    pub(crate) fn add_subscriber(&self, sender: Sender<NotificationEvent>) {
+        self.context.dispatcher.subscribe(sender);
+    }
+
+    /// Returns a stream of the identifiers and responses of all notifications
+    /// # Example
+    /// ```rust
+    /// use futures::executor::block_on;
+    /// use futures::StreamExt;
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider, NotificationResponse};
+    ///
+    /// let mock = MockBackend::new();
+    /// let provider = NotificationProvider::with_backend(mock.clone());
+    /// let mut responses = provider.responses();
+    ///
+    /// let id = Notification::new().title("Hello").send().unwrap();
+    /// mock.click(&id);
+    /// assert_eq!(
+    ///     block_on(responses.next()),
+    ///     Some((id, NotificationResponse::Click))
+    /// );
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn responses(&self) -> ResponseStream {
+        ResponseStream::new(self.context.clone())
+    }
+    /// Runs the main loop for .1 seconds. Commands queued by `NotificationHandle`s are executed first
     pub fn run_main_loop_once(&self) {
-        run_main_loop_once();
+        self.process_commands();
+        self.context.backend.run_main_loop_once();
     }
+
+    /// Returns a handle which can be used from any thread. Its commands are queued and
+    /// executed on the thread of the provider by `run_main_loop_once` or `process_commands`
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationHandle, NotificationProvider};
+    /// use std::thread;
+    ///
+    /// fn assert_send_sync<T: Send + Sync + Clone>(_: &T) {}
+    ///
+    /// let mock = MockBackend::new();
+    /// let provider = NotificationProvider::with_backend(mock.clone());
+    /// let handle: NotificationHandle = provider.handle();
+    /// assert_send_sync(&handle);
+    ///
+    /// let worker = thread::spawn(move || {
+    ///     handle
+    ///         .send(Notification::new().title("Sent by a worker"))
+    ///         .wait()
+    ///         .unwrap()
+    /// });
+    /// while !worker.is_finished() {
+    ///     provider.run_main_loop_once();
+    /// }
+    ///
+    /// let id = worker.join().unwrap().unwrap();
+    /// assert_eq!(mock.sent()[0].get_identifier(), id);
+    /// ```
+    pub fn handle(&self) -> NotificationHandle {
+        NotificationHandle::new(self.command_sender.clone())
+    }
+
+    pub(crate) fn send(&self, notification: Notification) -> Result<String, NotificationError> {
+        self.context.send(notification)
+    }
+
+    /// Executes all commands queued by `NotificationHandle`s without running the main loop
+    pub fn process_commands(&self) {
+        while let Ok(command) = self.commands.try_recv() {
+            handle::execute(self, command);
+        }
+    }
+
     /// Returns a vector of all notifications
     /// # Example
     /// ```rust
+    /// # #[cfg(target_os = "macos")] {
     /// use mac_notifications::{Notification, NotificationProvider};
     ///
     /// let mut provider = NotificationProvider::new("Terminal");
@@ -85,23 +333,15 @@ impl NotificationProvider {
     /// for notification in notifications {
     ///    println!("{:?}", notification);
     /// }
+    /// # }
+    /// ```
     pub fn get_all_notifications(&self) -> Vec<Notification> {
-        MainThreadMarker::new().expect("get_all_notificaitons() must be on the main thread");
-        let mut notifications = vec![];
-        unsafe {
-            let notification_center = NSUserNotificationCenter::defaultUserNotificationCenter();
-            let notifications_array = notification_center.deliveredNotifications();
-            let count = notifications_array.count();
-            for i in 0..count {
-                let notification = notifications_array.objectAtIndex(i);
-                notifications.push(Notification::from(notification.as_ref()));
-            }
-        }
-        notifications
+        self.context.backend.delivered_notifications()
     }
     /// Deletes a notification by its identifier
     /// # Example
     /// ```rust
+    /// # #[cfg(target_os = "macos")] {
     /// use mac_notifications::{Notification, NotificationProvider};
     ///
     /// let mut provider = NotificationProvider::new("Terminal");
@@ -113,27 +353,25 @@ impl NotificationProvider {
     /// }
     /// provider.delete(&id);
     /// let notifications = provider.get_all_notifications();
+    /// # }
     /// ```
     pub fn delete(&self, identifier: &str) {
-        MainThreadMarker::new().expect("delete() must be on the main thread");
-        unsafe {
-            let notification_center = NSUserNotificationCenter::defaultUserNotificationCenter();
-            let notifications_array = notification_center.deliveredNotifications();
-            let count = notifications_array.count();
-            for i in 0..count {
-                let notification = notifications_array.objectAtIndex(i);
-                let notification = notification.as_ref();
-                if let Some(id) = notification.identifier() {
-                    if id.to_string() == identifier {
-                        notification_center.removeDeliveredNotification(notification);
-                    }
-                }
-            }
+        self.context.backend.remove(identifier);
+        // recurring notifications keep their handler for the next repetition
+        let scheduled = self
+            .context
+            .backend
+            .scheduled_notifications()
+            .iter()
+            .any(|n| n.get_identifier() == identifier);
+        if !scheduled {
+            self.context.dispatcher.remove_handler(identifier);
         }
     }
     /// Deletes all notifications
     /// # Example
     /// ```rust
+    /// # #[cfg(target_os = "macos")] {
     /// use mac_notifications::{Notification, NotificationProvider};
     ///
     /// let mut provider = NotificationProvider::new("Terminal");
@@ -144,17 +382,204 @@ impl NotificationProvider {
     /// provider.delete_all();
     /// let notifications = provider.get_all_notifications();
     /// assert_eq!(notifications.len(), 0);
+    /// # }
     /// ```
     pub fn delete_all(&self) {
-        MainThreadMarker::new().expect("delete_all() must be on the main thread");
-        unsafe {
-            self.center.removeAllDeliveredNotifications();
+        self.context.backend.remove_all();
+        // scheduled notifications are not affected
+        let scheduled: Vec<String> = self
+            .context
+            .backend
+            .scheduled_notifications()
+            .iter()
+            .map(|n| n.get_identifier().to_string())
+            .collect();
+        self.context.dispatcher.retain_handlers(&scheduled);
+    }
+
+    /// Returns all notifications waiting for their delivery date
+    /// # Example
+    /// ```rust
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider};
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let provider = NotificationProvider::with_backend(MockBackend::new());
+    /// let in_an_hour = SystemTime::now() + Duration::from_secs(3600);
+    ///
+    /// let id = Notification::new().title("Standup").delivery_date(in_an_hour).send().unwrap();
+    /// Notification::new().title("Lunch").delivery_date(in_an_hour).send().unwrap();
+    /// assert_eq!(provider.get_scheduled_notifications().len(), 2);
+    ///
+    /// // move the standup by 10 minutes
+    /// let later = in_an_hour + Duration::from_secs(600);
+    /// provider.reschedule(&id, later).unwrap();
+    /// let standup = provider
+    ///     .get_scheduled_notifications()
+    ///     .into_iter()
+    ///     .find(|n| n.get_identifier() == id)
+    ///     .unwrap();
+    /// assert_eq!(standup.get_delivery_date(), Some(later));
+    ///
+    /// provider.cancel_scheduled(&id).unwrap();
+    /// assert_eq!(provider.get_scheduled_notifications().len(), 1);
+    /// assert!(provider.reschedule(&id, later).is_err());
+    ///
+    /// provider.cancel_all_scheduled().unwrap();
+    /// assert!(provider.get_scheduled_notifications().is_empty());
+    /// ```
+    pub fn get_scheduled_notifications(&self) -> Vec<Notification> {
+        self.context.backend.scheduled_notifications()
+    }
+
+    /// Cancels a scheduled notification before it is delivered.
+    /// Fails with `NotificationError::Store` if the attached store could not be written,
+    /// the notification is cancelled anyway
+    pub fn cancel_scheduled(&self, identifier: &str) -> Result<(), NotificationError> {
+        self.context.backend.remove_scheduled(identifier);
+        self.context.dispatcher.remove_handler(identifier);
+        #[cfg(feature = "store")]
+        self.context
+            .update_store(|store| store.remove(identifier))?;
+        Ok(())
+    }
+
+    /// Cancels all scheduled notifications, delivered notifications are not affected.
+    /// Fails like `cancel_scheduled`
+    pub fn cancel_all_scheduled(&self) -> Result<(), NotificationError> {
+        self.context.backend.remove_all_scheduled();
+        let delivered: Vec<String> = self
+            .context
+            .backend
+            .delivered_notifications()
+            .iter()
+            .map(|n| n.get_identifier().to_string())
+            .collect();
+        self.context.dispatcher.retain_handlers(&delivered);
+        #[cfg(feature = "store")]
+        self.context.update_store(|store| store.clear())?;
+        Ok(())
+    }
+
+    /// Moves a scheduled notification to a new delivery date.
+    /// Fails with `NotificationError::NotFound` if no scheduled notification has the identifier.
+    /// If the backend fails to schedule it, the notification is scheduled again at its old delivery date
+    pub fn reschedule(
+        &self,
+        identifier: &str,
+        delivery_date: SystemTime,
+    ) -> Result<(), NotificationError> {
+        let scheduled = self
+            .get_scheduled_notifications()
+            .into_iter()
+            .find(|n| n.get_identifier() == identifier)
+            .ok_or_else(|| NotificationError::NotFound(identifier.to_string()))?;
+        let notification = scheduled.clone().delivery_date(delivery_date);
+        notification.validate()?;
+        self.context.backend.remove_scheduled(identifier);
+        if let Err(err) = self.context.backend.schedule(&notification) {
+            // keeps the old delivery date, the store was not touched yet
+            let _ = self.context.backend.schedule(&scheduled);
+            return Err(err);
         }
+        #[cfg(feature = "store")]
+        self.context
+            .update_store(|store| store.insert(&notification))?;
+        Ok(())
+    }
+
+    /// Keeps the scheduled and recurring notifications of this provider in the store from now on.
+    ///
+    /// Stored notifications the backend does not have anymore are scheduled again, missed ones
+    /// are delivered or dropped according to `catch_up`. See the `store` module for an example
+    #[cfg(feature = "store")]
+    pub fn attach_store(
+        &self,
+        mut store: ScheduleStore,
+        catch_up: CatchUp,
+    ) -> Result<Reconciliation, NotificationError> {
+        // not attached yet, so events of the backend during the reconciliation do not touch the store
+        let report = store.reconcile(&*self.context.backend, catch_up, SystemTime::now())?;
+        *self.context.store.borrow_mut() = Some(store);
+        Ok(report)
     }
 }
+/// Builds a `NotificationProvider` using the NSUserNotification backend, created with `NotificationProvider::builder`.
+///
+/// An explicit bundle identifier is preferred over the application name and has to be found by `AppBundleResolver`.
+/// The application name is only used if it is found, otherwise the Finder is used as a fallback
+#[cfg(target_os = "macos")]
+#[derive(Debug, Clone)]
+pub struct NotificationProviderBuilder {
+    bundle_identifier: Option<String>,
+    app_name: Option<String>,
+    fallback: bool,
+    resolver: AppBundleResolver,
+}
+
+#[cfg(target_os = "macos")]
+impl Default for NotificationProviderBuilder {
+    fn default() -> Self {
+        Self {
+            bundle_identifier: None,
+            app_name: None,
+            fallback: true,
+            resolver: AppBundleResolver::default(),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl NotificationProviderBuilder {
+    /// Sends the notifications on behalf of the application with this bundle identifier e.g. "com.apple.Terminal"
+    pub fn bundle_identifier(mut self, bundle_identifier: &str) -> Self {
+        self.bundle_identifier = Some(bundle_identifier.to_string());
+        self
+    }
+
+    /// Sends the notifications on behalf of the application with this name e.g. "Terminal"
+    pub fn app_name(mut self, app_name: &str) -> Self {
+        self.app_name = Some(app_name.to_string());
+        self
+    }
+
+    /// Whether the Finder is used if the application name could not be resolved, enabled by default.
+    /// If disabled `build` fails with `NotificationError::AppNotFound` or `NotificationError::BundleNotResolved` instead.
+    /// A bundle identifier which is not installed always fails with `NotificationError::AppNotFound`
+    pub fn fallback(mut self, fallback: bool) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Sets the directories installed applications are searched in
+    pub fn resolver(mut self, resolver: AppBundleResolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Resolves the bundle identifier and creates the provider
+    /// # Panics
+    /// Panics if the function is not called on the main thread
+    pub fn build(self) -> Result<NotificationProvider, NotificationError> {
+        MainThreadMarker::new().expect("build() must be on the main thread");
+        let resolution = self.resolver.resolve(
+            self.bundle_identifier.as_deref(),
+            self.app_name.as_deref(),
+            self.fallback,
+        )?;
+        if !bundle::sys::set_bundle_identifier(resolution.identifier()) {
+            return Err(NotificationError::BundleNotResolved);
+        }
+        let mut provider = NotificationProvider::with_backend(MacOSBackend::new());
+        provider.bundle = Some(resolution);
+        Ok(provider)
+    }
+}
+
 /// Runs the main loop for .1 seconds
 /// # Panics
 /// Panics if the function is not called on the main thread
+#[cfg(target_os = "macos")]
 pub fn run_main_loop_once() {
     MainThreadMarker::new().expect("run_main_loop_once() must be on the main thread");
 
@@ -166,16 +591,14 @@ pub fn run_main_loop_once() {
 }
 impl Drop for NotificationProvider {
     fn drop(&mut self) {
-        unsafe {
-            let notification_center = NSUserNotificationCenter::defaultUserNotificationCenter();
-            if let Some(delegate) = notification_center.delegate() {
-                if delegate
-                    .as_ref()
-                    .isKindOfClass(RustNotificationDelegate::class())
-                {
-                    notification_center.setDelegate(None);
-                }
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            if current
+                .as_ref()
+                .is_some_and(|context| Rc::ptr_eq(context, &self.context))
+            {
+                *current = None;
             }
-        }
+        });
     }
 }
@@ -0,0 +1,295 @@
+use chrono::{DateTime, Datelike, Local, TimeDelta, TimeZone, Utc};
+use std::time::{Duration, SystemTime};
+
+/// Day of the week for `Recurrence::Weekly`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Weekday of the date in the local time zone
+    pub fn of(date: SystemTime) -> Self {
+        Self::of_in(date, &Local)
+    }
+
+    fn of_in<Tz: TimeZone>(date: SystemTime, zone: &Tz) -> Self {
+        match DateTime::<Utc>::from(date).with_timezone(zone).weekday() {
+            chrono::Weekday::Mon => Weekday::Monday,
+            chrono::Weekday::Tue => Weekday::Tuesday,
+            chrono::Weekday::Wed => Weekday::Wednesday,
+            chrono::Weekday::Thu => Weekday::Thursday,
+            chrono::Weekday::Fri => Weekday::Friday,
+            chrono::Weekday::Sat => Weekday::Saturday,
+            chrono::Weekday::Sun => Weekday::Sunday,
+        }
+    }
+
+    /// 1 for Monday up to 7 for Sunday
+    pub fn number(&self) -> u8 {
+        *self as u8 + 1
+    }
+
+    /// Inverse of `number`
+    pub fn from_number(number: u8) -> Option<Self> {
+        [
+            Weekday::Monday,
+            Weekday::Tuesday,
+            Weekday::Wednesday,
+            Weekday::Thursday,
+            Weekday::Friday,
+            Weekday::Saturday,
+            Weekday::Sunday,
+        ]
+        .get(usize::from(number).checked_sub(1)?)
+        .copied()
+    }
+}
+
+/// How often a notification is repeated after its first delivery
+/// # Example
+/// ```rust
+/// use mac_notifications::{Recurrence, Weekday};
+/// use std::time::{Duration, SystemTime};
+///
+/// let now = SystemTime::now();
+/// assert_eq!(
+///     Recurrence::Minutes(15).next_delivery(now),
+///     now + Duration::from_secs(15 * 60)
+/// );
+///
+/// // stand-up on working days
+/// let standup = Recurrence::Weekly(vec![
+///     Weekday::Monday,
+///     Weekday::Tuesday,
+///     Weekday::Wednesday,
+///     Weekday::Thursday,
+///     Weekday::Friday,
+/// ]);
+/// let first = standup.first_delivery(now);
+/// let next = standup.next_delivery(first);
+/// assert!(first >= now && next > first);
+/// assert_ne!(Weekday::of(next), Weekday::Saturday);
+/// assert_ne!(Weekday::of(next), Weekday::Sunday);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Recurrence {
+    /// Every n minutes
+    Minutes(u32),
+    /// Every n hours
+    Hours(u32),
+    /// Every n days at the same local time
+    Days(u32),
+    /// Every week on the given days at the same local time
+    Weekly(Vec<Weekday>),
+}
+
+impl Recurrence {
+    /// False for an interval of zero or a week without days
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Recurrence::Minutes(n) | Recurrence::Hours(n) | Recurrence::Days(n) => *n > 0,
+            Recurrence::Weekly(days) => !days.is_empty(),
+        }
+    }
+
+    /// The first delivery at or after `start`. Only differs from `start` for `Weekly`,
+    /// which waits for the first of its days
+    pub fn first_delivery(&self, start: SystemTime) -> SystemTime {
+        self.first_delivery_in(start, &Local)
+    }
+
+    fn first_delivery_in<Tz: TimeZone>(&self, start: SystemTime, zone: &Tz) -> SystemTime {
+        match self {
+            Recurrence::Weekly(days) => (0..7)
+                .map(|n| shift_days(start, n, zone))
+                .find(|date| days.contains(&Weekday::of_in(*date, zone)))
+                .unwrap_or(start),
+            _ => start,
+        }
+    }
+
+    /// The delivery following the one at `previous`
+    pub fn next_delivery(&self, previous: SystemTime) -> SystemTime {
+        self.next_delivery_in(previous, &Local)
+    }
+
+    fn next_delivery_in<Tz: TimeZone>(&self, previous: SystemTime, zone: &Tz) -> SystemTime {
+        match self {
+            Recurrence::Minutes(n) => previous + Duration::from_secs(u64::from(*n) * 60),
+            Recurrence::Hours(n) => previous + Duration::from_secs(u64::from(*n) * 3600),
+            Recurrence::Days(n) => shift_days(previous, *n, zone),
+            Recurrence::Weekly(days) => (1..=7)
+                .map(|n| shift_days(previous, n, zone))
+                .find(|date| days.contains(&Weekday::of_in(*date, zone)))
+                .unwrap_or_else(|| shift_days(previous, 7, zone)),
+        }
+    }
+
+    /// Compact form stored alongside NSUserNotifications, e.g. `weekly:1,5`
+    #[cfg(target_os = "macos")]
+    pub(crate) fn encode(&self) -> String {
+        match self {
+            Recurrence::Minutes(n) => format!("minutes:{}", n),
+            Recurrence::Hours(n) => format!("hours:{}", n),
+            Recurrence::Days(n) => format!("days:{}", n),
+            Recurrence::Weekly(days) => {
+                let days: Vec<String> = days.iter().map(|day| day.number().to_string()).collect();
+                format!("weekly:{}", days.join(","))
+            }
+        }
+    }
+
+    /// Inverse of `encode`
+    #[cfg(target_os = "macos")]
+    pub(crate) fn decode(value: &str) -> Option<Self> {
+        let (kind, value) = value.split_once(':')?;
+        match kind {
+            "minutes" => value.parse().ok().map(Recurrence::Minutes),
+            "hours" => value.parse().ok().map(Recurrence::Hours),
+            "days" => value.parse().ok().map(Recurrence::Days),
+            "weekly" => value
+                .split(',')
+                .map(|day| day.parse().ok().and_then(Weekday::from_number))
+                .collect::<Option<Vec<_>>>()
+                .map(Recurrence::Weekly),
+            _ => None,
+        }
+    }
+}
+
+/// Moves the date by whole days keeping the time of day in the zone, also across daylight saving time changes.
+/// A time of day skipped by the change is moved by whole 24 hours instead
+fn shift_days<Tz: TimeZone>(date: SystemTime, days: u32, zone: &Tz) -> SystemTime {
+    let local = DateTime::<Utc>::from(date)
+        .with_timezone(zone)
+        .naive_local()
+        + TimeDelta::days(i64::from(days));
+    zone.from_local_datetime(&local)
+        .earliest()
+        .map(SystemTime::from)
+        .unwrap_or_else(|| date + Duration::from_secs(u64::from(days) * 86400))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Berlin;
+
+    /// A local time in Berlin, where daylight saving time started on 2024-03-31 and ended on 2024-10-27
+    fn berlin(date: &str) -> SystemTime {
+        let local = chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+        Berlin
+            .from_local_datetime(&local)
+            .earliest()
+            .unwrap()
+            .into()
+    }
+
+    fn hours_between(from: SystemTime, to: SystemTime) -> u64 {
+        to.duration_since(from).unwrap().as_secs() / 3600
+    }
+
+    #[test]
+    fn days_keep_the_time_of_day_across_daylight_saving_time() {
+        let spring = berlin("2024-03-30 09:00");
+        let next = Recurrence::Days(1).next_delivery_in(spring, &Berlin);
+        assert_eq!(next, berlin("2024-03-31 09:00"));
+        assert_eq!(hours_between(spring, next), 23);
+
+        let autumn = berlin("2024-10-26 09:00");
+        let next = Recurrence::Days(1).next_delivery_in(autumn, &Berlin);
+        assert_eq!(next, berlin("2024-10-27 09:00"));
+        assert_eq!(hours_between(autumn, next), 25);
+
+        // hours are not tied to the time of day
+        let next = Recurrence::Hours(24).next_delivery_in(spring, &Berlin);
+        assert_eq!(next, berlin("2024-03-31 10:00"));
+    }
+
+    #[test]
+    fn skipped_times_of_day_move_by_whole_days() {
+        // 02:30 does not exist on 2024-03-31
+        let start = berlin("2024-03-30 02:30");
+        let next = shift_days(start, 1, &Berlin);
+        assert_eq!(next, berlin("2024-03-31 03:30"));
+        assert_eq!(shift_days(next, 1, &Berlin), berlin("2024-04-01 03:30"));
+        // 02:30 exists twice on 2024-10-27, the first one is used
+        let ambiguous = shift_days(berlin("2024-10-26 02:30"), 1, &Berlin);
+        assert_eq!(hours_between(berlin("2024-10-26 02:30"), ambiguous), 24);
+    }
+
+    #[test]
+    fn weeks_wrap_around() {
+        // 2024-06-07 is a Friday
+        let friday = berlin("2024-06-07 18:00");
+        let weekly = Recurrence::Weekly(vec![Weekday::Monday, Weekday::Friday]);
+        assert_eq!(weekly.first_delivery_in(friday, &Berlin), friday);
+        let monday = weekly.next_delivery_in(friday, &Berlin);
+        assert_eq!(monday, berlin("2024-06-10 18:00"));
+        assert_eq!(
+            weekly.next_delivery_in(monday, &Berlin),
+            berlin("2024-06-14 18:00")
+        );
+
+        let saturday = berlin("2024-06-08 18:00");
+        assert_eq!(weekly.first_delivery_in(saturday, &Berlin), monday);
+
+        let sunday = Recurrence::Weekly(vec![Weekday::Sunday]);
+        let first = sunday.first_delivery_in(friday, &Berlin);
+        assert_eq!(first, berlin("2024-06-09 18:00"));
+        assert_eq!(
+            sunday.next_delivery_in(first, &Berlin),
+            berlin("2024-06-16 18:00")
+        );
+    }
+
+    #[test]
+    fn weekly_split_by_weekday_matches_the_recurrence() {
+        // macOS repeats every weekday in its own `#weekday-N` notification, starting at its first delivery
+        let days = vec![Weekday::Monday, Weekday::Wednesday, Weekday::Saturday];
+        let start = berlin("2024-03-20 08:00");
+        let mut split: Vec<SystemTime> = days
+            .iter()
+            .flat_map(|day| {
+                let part = Recurrence::Weekly(vec![*day]);
+                let first = part.first_delivery_in(start, &Berlin);
+                std::iter::successors(Some(first), move |date| {
+                    Some(part.next_delivery_in(*date, &Berlin))
+                })
+                .take(3)
+            })
+            .collect();
+        split.sort();
+
+        let weekly = Recurrence::Weekly(days);
+        let combined: Vec<SystemTime> =
+            std::iter::successors(Some(weekly.first_delivery_in(start, &Berlin)), |date| {
+                Some(weekly.next_delivery_in(*date, &Berlin))
+            })
+            .take(9)
+            .collect();
+        assert_eq!(split, combined);
+        assert_eq!(combined[0], berlin("2024-03-20 08:00"));
+        // the first delivery after the change to summer time keeps 08:00
+        assert_eq!(combined[5], berlin("2024-04-01 08:00"));
+        assert!(combined.iter().all(|date| time_of_day(*date) == "08:00"));
+    }
+
+    fn time_of_day(date: SystemTime) -> String {
+        DateTime::<Utc>::from(date)
+            .with_timezone(&Berlin)
+            .format("%H:%M")
+            .to_string()
+    }
+}
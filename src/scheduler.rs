@@ -0,0 +1,445 @@
+//! # Scheduler
+//! Sends notifications following cron expressions, e.g. a stand-up reminder every working day at 9:30.
+//!
+//! Fire times are computed in pure Rust from the current time of a `Clock`.
+//! `ManualClock` replaces the system time in tests, so schedules can be checked without waiting.
+
+use crate::{Notification, NotificationError};
+use chrono::{DateTime, Utc};
+pub use chrono_tz::Tz;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// The time of the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which only moves when told to. Clones share the same time
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<SystemTime>>,
+}
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Rc::new(Cell::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.now.get()
+    }
+}
+
+/// A cron expression evaluated in a time zone
+///
+/// Expressions have the fields `sec min hour day-of-month month day-of-week [year]`,
+/// the usual five fields without seconds are accepted as well and fire at second 0.
+/// # Example
+/// ```rust
+/// use mac_notifications::scheduler::{CronSchedule, Tz};
+/// use std::time::{Duration, SystemTime};
+///
+/// // 2024-01-01 is a Monday, 12:00 UTC is 13:00 in Berlin
+/// let monday_noon = SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_110_400);
+/// let schedule = CronSchedule::new("30 9 * * Mon-Fri", Tz::Europe__Berlin).unwrap();
+///
+/// // Tuesday 9:30 in Berlin is 8:30 UTC
+/// let tuesday = SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_184_200);
+/// assert_eq!(schedule.next_after(monday_noon), Some(tuesday));
+/// assert_eq!(schedule.upcoming(monday_noon).take(5).count(), 5);
+///
+/// assert!(CronSchedule::new("every monday", Tz::UTC).is_err());
+/// assert!(CronSchedule::parse("0 9 * * *", "Mars/Olympus_Mons").is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    schedule: cron::Schedule,
+    timezone: Tz,
+}
+
+impl CronSchedule {
+    pub fn new(expression: &str, timezone: Tz) -> Result<Self, NotificationError> {
+        let expression = match expression.split_whitespace().count() {
+            5 => format!("0 {}", expression),
+            _ => expression.to_string(),
+        };
+        let schedule = cron::Schedule::from_str(&expression)
+            .map_err(|err| NotificationError::InvalidSchedule(err.to_string()))?;
+        Ok(Self { schedule, timezone })
+    }
+
+    /// Like `new`, with the name of the time zone e.g. "Europe/Berlin"
+    pub fn parse(expression: &str, timezone: &str) -> Result<Self, NotificationError> {
+        let timezone = Tz::from_str(timezone).map_err(|_| {
+            NotificationError::InvalidSchedule(format!("unknown time zone {}", timezone))
+        })?;
+        Self::new(expression, timezone)
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    /// The first fire time strictly after `time`, `None` if the schedule has ended
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        self.upcoming(time).next()
+    }
+
+    /// All fire times strictly after `time`
+    pub fn upcoming(&self, time: SystemTime) -> impl Iterator<Item = SystemTime> + '_ {
+        let after = DateTime::<Utc>::from(time).with_timezone(&self.timezone);
+        self.schedule
+            .after(&after)
+            .map(|date| SystemTime::from(date.with_timezone(&Utc)))
+    }
+}
+
+struct Job {
+    name: String,
+    schedule: CronSchedule,
+    template: Notification,
+    next: Option<SystemTime>,
+}
+
+/// Sends copies of notification templates whenever their cron schedule fires.
+///
+/// The scheduler does not run by itself, call `run_pending` regularly, e.g. next to
+/// `NotificationProvider::run_main_loop_once`. Every copy gets its own identifier and is delivered right away.
+/// Fire times which passed while `run_pending` was not called are sent once, not once per missed time
+/// # Example
+/// ```rust
+/// use mac_notifications::backend::MockBackend;
+/// use mac_notifications::scheduler::{CronSchedule, ManualClock, Scheduler, Tz};
+/// use mac_notifications::{Notification, NotificationProvider};
+/// use std::time::{Duration, SystemTime};
+///
+/// let provider = NotificationProvider::with_backend(MockBackend::new());
+///
+/// // 2024-01-01 00:00 UTC
+/// let clock = ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200));
+/// let mut scheduler = Scheduler::with_clock(clock.clone());
+/// scheduler
+///     .add(
+///         "standup",
+///         CronSchedule::new("0 9 * * *", Tz::UTC).unwrap(),
+///         Notification::new().title("Stand-up"),
+///     )
+///     .unwrap();
+///
+/// let (name, first) = scheduler.next_fire_time().unwrap();
+/// assert_eq!(name, "standup");
+/// assert!(scheduler.run_pending().is_empty());
+///
+/// clock.set(first);
+/// let sent = scheduler.run_pending();
+/// assert_eq!(sent.len(), 1);
+/// let delivered = provider.get_all_notifications();
+/// assert_eq!(delivered[0].get_title(), Some("Stand-up"));
+/// assert!(provider.get_scheduled_notifications().is_empty());
+///
+/// // the next one is due a day later
+/// assert_eq!(
+///     scheduler.next_fire_time().unwrap().1,
+///     first + Duration::from_secs(24 * 3600)
+/// );
+/// ```
+pub struct Scheduler<C: Clock = SystemClock> {
+    clock: C,
+    jobs: Vec<Job>,
+}
+
+impl Scheduler<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for Scheduler<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            clock,
+            jobs: vec![],
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Adds a job, a previous job with the same name gets replaced.
+    /// Fails with `NotificationError::InvalidSchedule` if the schedule never fires again
+    /// or the template has a delivery date or recurrence of its own
+    pub fn add(
+        &mut self,
+        name: &str,
+        schedule: CronSchedule,
+        template: Notification,
+    ) -> Result<(), NotificationError> {
+        if template.is_scheduled() {
+            return Err(NotificationError::InvalidSchedule(format!(
+                "{} has a delivery date or recurrence",
+                name
+            )));
+        }
+        let next = schedule
+            .next_after(self.clock.now())
+            .ok_or_else(|| NotificationError::InvalidSchedule(format!("{} never fires", name)))?;
+        self.remove(name);
+        self.jobs.push(Job {
+            name: name.to_string(),
+            schedule,
+            template,
+            next: Some(next),
+        });
+        Ok(())
+    }
+
+    /// Removes a job, false if there is none with this name
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.jobs.len();
+        self.jobs.retain(|job| job.name != name);
+        self.jobs.len() != count
+    }
+
+    /// Names of all jobs
+    pub fn jobs(&self) -> impl Iterator<Item = &str> {
+        self.jobs.iter().map(|job| job.name.as_str())
+    }
+
+    /// The job which fires next and when
+    pub fn next_fire_time(&self) -> Option<(&str, SystemTime)> {
+        self.jobs
+            .iter()
+            .filter_map(|job| Some((job.name.as_str(), job.next?)))
+            .min_by_key(|(_, next)| *next)
+    }
+
+    /// Returns the fire times and notifications of all jobs which are due and moves the jobs to their next fire time
+    pub fn due(&mut self) -> Vec<(SystemTime, Notification)> {
+        let now = self.clock.now();
+        let mut due = vec![];
+        for job in self.jobs.iter_mut() {
+            let Some(fire_time) = job.next.filter(|next| *next <= now) else {
+                continue;
+            };
+            job.next = job.schedule.next_after(now);
+            due.push((fire_time, job.template.clone().renew_identifier()));
+        }
+        due
+    }
+
+    /// Sends the notifications of all due jobs through the current `NotificationProvider`
+    pub fn run_pending(&mut self) -> Vec<Result<String, NotificationError>> {
+        self.due()
+            .into_iter()
+            .map(|(_, notification)| notification.send())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> SystemTime {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn five_and_six_fields() {
+        let five = CronSchedule::new("15 10 * * *", Tz::UTC).unwrap();
+        let six = CronSchedule::new("0 15 10 * * *", Tz::UTC).unwrap();
+        let start = utc(2024, 1, 1, 0, 0);
+        assert_eq!(five.next_after(start), Some(utc(2024, 1, 1, 10, 15)));
+        assert_eq!(five.next_after(start), six.next_after(start));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for expression in ["", "* * *", "61 * * * *", "0 25 * * *", "0 9 * * Funday"] {
+            assert!(
+                matches!(
+                    CronSchedule::new(expression, Tz::UTC),
+                    Err(NotificationError::InvalidSchedule(_))
+                ),
+                "{:?} was accepted",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn next_after_is_strictly_after() {
+        let schedule = CronSchedule::new("0 9 * * *", Tz::UTC).unwrap();
+        let nine = utc(2024, 1, 1, 9, 0);
+        assert_eq!(schedule.next_after(nine), Some(utc(2024, 1, 2, 9, 0)));
+    }
+
+    #[test]
+    fn leap_day() {
+        let schedule = CronSchedule::new("0 12 29 2 *", Tz::UTC).unwrap();
+        let upcoming: Vec<SystemTime> = schedule.upcoming(utc(2023, 1, 1, 0, 0)).take(2).collect();
+        assert_eq!(
+            upcoming,
+            vec![utc(2024, 2, 29, 12, 0), utc(2028, 2, 29, 12, 0)]
+        );
+    }
+
+    #[test]
+    fn follows_daylight_saving_time() {
+        let schedule = CronSchedule::new("0 9 * * *", Tz::Europe__Berlin).unwrap();
+        // 9:00 is 8:00 UTC in winter and 7:00 UTC in summer, the clocks moved on 2024-03-31
+        let upcoming: Vec<SystemTime> = schedule.upcoming(utc(2024, 3, 30, 0, 0)).take(2).collect();
+        assert_eq!(
+            upcoming,
+            vec![utc(2024, 3, 30, 8, 0), utc(2024, 3, 31, 7, 0)]
+        );
+    }
+
+    #[test]
+    fn skipped_local_time_does_not_fire() {
+        // 2:30 does not exist in Berlin on 2024-03-31
+        let schedule = CronSchedule::new("30 2 * * *", Tz::Europe__Berlin).unwrap();
+        let next = schedule.next_after(utc(2024, 3, 30, 12, 0));
+        assert_eq!(next, Some(utc(2024, 4, 1, 0, 30)));
+    }
+
+    #[test]
+    fn ended_schedule_is_rejected() {
+        let clock = ManualClock::new(utc(2024, 1, 1, 0, 0));
+        let mut scheduler = Scheduler::with_clock(clock);
+        let schedule = CronSchedule::new("0 0 0 1 1 * 2020", Tz::UTC).unwrap();
+        assert_eq!(schedule.next_after(utc(2024, 1, 1, 0, 0)), None);
+        assert!(matches!(
+            scheduler.add("past", schedule, Notification::new()),
+            Err(NotificationError::InvalidSchedule(_))
+        ));
+        assert_eq!(scheduler.jobs().count(), 0);
+    }
+
+    #[test]
+    fn missed_fire_times_are_due_once() {
+        let clock = ManualClock::new(utc(2024, 1, 1, 0, 0));
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        let hourly = CronSchedule::new("0 * * * *", Tz::UTC).unwrap();
+        scheduler
+            .add("hourly", hourly, Notification::new())
+            .unwrap();
+
+        clock.set(utc(2024, 1, 1, 5, 30));
+        let due = scheduler.due();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, utc(2024, 1, 1, 1, 0));
+        assert!(scheduler.due().is_empty());
+        assert_eq!(
+            scheduler.next_fire_time(),
+            Some(("hourly", utc(2024, 1, 1, 6, 0)))
+        );
+    }
+
+    #[test]
+    fn copies_get_their_own_identifier() {
+        let clock = ManualClock::new(utc(2024, 1, 1, 0, 0));
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        let template = Notification::new().title("Tick");
+        let every_minute = CronSchedule::new("* * * * *", Tz::UTC).unwrap();
+        scheduler
+            .add("tick", every_minute, template.clone())
+            .unwrap();
+
+        clock.advance(Duration::from_secs(60));
+        let (_, first) = scheduler.due().remove(0);
+        clock.advance(Duration::from_secs(60));
+        let (_, second) = scheduler.due().remove(0);
+        assert_ne!(first.get_identifier(), second.get_identifier());
+        assert_ne!(first.get_identifier(), template.get_identifier());
+    }
+
+    #[test]
+    fn due_jobs_are_delivered_right_away() {
+        let mock = crate::backend::MockBackend::new();
+        let provider = crate::NotificationProvider::with_backend(mock.clone());
+        let clock = ManualClock::new(utc(2024, 1, 1, 0, 0));
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        let every_minute = CronSchedule::new("* * * * *", Tz::UTC).unwrap();
+        scheduler
+            .add("tick", every_minute, Notification::new().title("Tick"))
+            .unwrap();
+
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(60));
+            assert!(scheduler.run_pending()[0].is_ok());
+        }
+        assert_eq!(provider.get_all_notifications().len(), 3);
+        assert!(provider.get_scheduled_notifications().is_empty());
+        assert!(mock.sent().iter().all(|n| n.get_delivery_date().is_none()));
+    }
+
+    #[test]
+    fn scheduled_templates_are_rejected() {
+        let mut scheduler = Scheduler::with_clock(ManualClock::new(utc(2024, 1, 1, 0, 0)));
+        let daily = CronSchedule::new("0 9 * * *", Tz::UTC).unwrap();
+        let template = Notification::new().delivery_date(utc(2024, 1, 2, 0, 0));
+        assert!(matches!(
+            scheduler.add("dated", daily, template),
+            Err(NotificationError::InvalidSchedule(_))
+        ));
+        assert_eq!(scheduler.jobs().count(), 0);
+    }
+
+    #[test]
+    fn adding_a_job_with_the_same_name_replaces_it() {
+        let clock = ManualClock::new(utc(2024, 1, 1, 0, 0));
+        let mut scheduler = Scheduler::with_clock(clock);
+        let daily = CronSchedule::new("0 9 * * *", Tz::UTC).unwrap();
+        let weekly = CronSchedule::new("0 9 * * Fri", Tz::UTC).unwrap();
+        scheduler
+            .add("reminder", daily, Notification::new())
+            .unwrap();
+        scheduler
+            .add("reminder", weekly, Notification::new())
+            .unwrap();
+        assert_eq!(scheduler.jobs().collect::<Vec<_>>(), vec!["reminder"]);
+        // 2024-01-05 is the first Friday
+        assert_eq!(
+            scheduler.next_fire_time(),
+            Some(("reminder", utc(2024, 1, 5, 9, 0)))
+        );
+        assert!(scheduler.remove("reminder"));
+        assert!(!scheduler.remove("reminder"));
+    }
+}
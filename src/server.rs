@@ -0,0 +1,64 @@
+//! The `tiny_http` loop shared by `http::HttpServer` and `alertmanager::AlertmanagerReceiver`,
+//! both answer every request with a JSON object.
+
+use std::io::{self, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Request, Response, Server};
+
+/// The status code and the description of a failed request
+pub(crate) type Failure = (u16, String);
+
+/// Answers the requests of a `JsonServer`
+pub(crate) trait Handler: Send + 'static {
+    /// The status code and the JSON answer to the request
+    fn respond(&self, request: &mut Request) -> (u16, serde_json::Value);
+}
+
+pub(crate) struct JsonServer<H> {
+    server: Server,
+    pub(crate) handler: H,
+}
+
+impl<H: Handler> JsonServer<H> {
+    pub(crate) fn bind<A: ToSocketAddrs>(address: A, handler: H) -> io::Result<Self> {
+        let server = Server::http(address).map_err(io::Error::other)?;
+        Ok(Self { server, handler })
+    }
+
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    pub(crate) fn run(&self) -> io::Result<()> {
+        loop {
+            let mut request = self.server.recv()?;
+            let (status, body) = self.handler.respond(&mut request);
+            let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .expect("the header is valid ASCII");
+            let response = Response::from_string(body.to_string())
+                .with_status_code(status)
+                .with_header(header);
+            // a client which hung up does not need an answer anymore
+            let _ = request.respond(response);
+        }
+    }
+
+    pub(crate) fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+/// Reads the body, fails with 413 if it is bigger than `limit`
+pub(crate) fn read_body(request: &mut Request, limit: usize) -> Result<Vec<u8>, Failure> {
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(limit as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|err| (400, format!("invalid body: {}", err)))?;
+    if body.len() > limit {
+        return Err((413, format!("the body is bigger than {} bytes", limit)));
+    }
+    Ok(body)
+}
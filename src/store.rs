@@ -0,0 +1,430 @@
+//! # Schedule store
+//! Scheduled notifications only live as long as the OS (or the process for emulated schedules) keeps them.
+//! A `ScheduleStore` writes every scheduled and recurring notification of a provider to a JSON file,
+//! so they can be restored after a restart with `NotificationProvider::attach_store`.
+//! The notifications are written like their `serde` serialization.
+//!
+//! Handlers registered with `Notification::on_response` are not stored.
+
+use crate::backend::NotificationBackend;
+use crate::{Notification, NotificationError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// What happens to notifications whose delivery date passed while nobody was running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CatchUp {
+    /// Missed notifications are dropped
+    Skip,
+    /// Missed notifications are delivered once, no matter how late
+    #[default]
+    FireOnce,
+    /// Missed notifications are delivered if they are at most this late, otherwise dropped
+    FireWithin(Duration),
+}
+
+/// What `NotificationProvider::attach_store` did with the stored notifications
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// Still scheduled by the backend, nothing to do
+    pub pending: Vec<String>,
+    /// Scheduled again, because the backend lost them or they repeat
+    pub rescheduled: Vec<String>,
+    /// Missed and delivered now according to the `CatchUp` policy
+    pub fired: Vec<String>,
+    /// Missed and dropped according to the `CatchUp` policy
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    notifications: Vec<Notification>,
+}
+
+const VERSION: u32 = 1;
+
+/// Reads a file written with `VERSION`, the version is checked before the notifications are read
+fn parse(content: &[u8]) -> Result<Vec<Notification>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Header {
+        version: u32,
+    }
+    let Header { version } = serde_json::from_slice(content)?;
+    if version != VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "unsupported version {}",
+            version
+        )));
+    }
+    let file: StoreFile = serde_json::from_slice(content)?;
+    Ok(file.notifications)
+}
+
+/// JSON file of the scheduled and recurring notifications of a provider
+/// # Example
+/// ```rust
+/// use mac_notifications::backend::MockBackend;
+/// use mac_notifications::store::{CatchUp, ScheduleStore};
+/// use mac_notifications::{Notification, NotificationProvider};
+/// use std::time::{Duration, SystemTime};
+///
+/// let path = std::env::temp_dir().join(format!("schedule-{}.json", std::process::id()));
+/// let in_an_hour = SystemTime::now() + Duration::from_secs(3600);
+///
+/// // the first run schedules a notification and exits
+/// {
+///     let provider = NotificationProvider::with_backend(MockBackend::new());
+///     provider
+///         .attach_store(ScheduleStore::open(&path).unwrap(), CatchUp::FireOnce)
+///         .unwrap();
+///     Notification::new()
+///         .title("Timesheet")
+///         .delivery_date(in_an_hour)
+///         .send()
+///         .unwrap();
+/// }
+///
+/// // the next run schedules it again
+/// let mock = MockBackend::new();
+/// let provider = NotificationProvider::with_backend(mock.clone());
+/// let store = ScheduleStore::open(&path).unwrap();
+/// assert_eq!(store.notifications().len(), 1);
+/// let report = provider.attach_store(store, CatchUp::FireOnce).unwrap();
+/// assert_eq!(report.rescheduled.len(), 1);
+/// assert_eq!(
+///     provider.get_scheduled_notifications()[0].get_title(),
+///     Some("Timesheet")
+/// );
+///
+/// // once delivered it is removed from the store
+/// mock.deliver_scheduled();
+/// assert!(ScheduleStore::open(&path).unwrap().notifications().is_empty());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ScheduleStore {
+    path: PathBuf,
+    notifications: Vec<Notification>,
+}
+
+impl ScheduleStore {
+    /// Reads the store from the file, a missing file is an empty store
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, NotificationError> {
+        let path = path.as_ref().to_path_buf();
+        let notifications = match fs::read(&path) {
+            Ok(content) => {
+                parse(&content).map_err(|err| NotificationError::Store(err.to_string()))?
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(NotificationError::Store(err.to_string())),
+        };
+        Ok(Self {
+            path,
+            notifications,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// All stored notifications
+    pub fn notifications(&self) -> Vec<Notification> {
+        self.notifications.clone()
+    }
+
+    pub fn contains(&self, identifier: &str) -> bool {
+        self.notifications
+            .iter()
+            .any(|n| n.get_identifier() == identifier)
+    }
+
+    /// Adds or replaces a notification
+    pub(crate) fn insert(&mut self, notification: &Notification) -> Result<(), NotificationError> {
+        match self
+            .notifications
+            .iter_mut()
+            .find(|n| n.get_identifier() == notification.get_identifier())
+        {
+            Some(existing) => *existing = notification.clone(),
+            None => self.notifications.push(notification.clone()),
+        }
+        self.save()
+    }
+
+    pub(crate) fn remove(&mut self, identifier: &str) -> Result<(), NotificationError> {
+        let count = self.notifications.len();
+        self.notifications
+            .retain(|n| n.get_identifier() != identifier);
+        if self.notifications.len() == count {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    pub(crate) fn clear(&mut self) -> Result<(), NotificationError> {
+        self.notifications.clear();
+        self.save()
+    }
+
+    /// Removes a delivered notification, recurring ones move on to their next repetition
+    pub(crate) fn delivered(
+        &mut self,
+        identifier: &str,
+        now: SystemTime,
+    ) -> Result<(), NotificationError> {
+        let Some(next) = self
+            .notifications
+            .iter()
+            .find(|n| n.get_identifier() == identifier)
+            .map(|n| n.next_occurrence(now))
+        else {
+            return Ok(());
+        };
+        match next {
+            Some(next) => self.insert(&next),
+            None => self.remove(identifier),
+        }
+    }
+
+    /// Writes the store to a temporary file first, so a crash never leaves a truncated store behind
+    fn save(&self) -> Result<(), NotificationError> {
+        let file = StoreFile {
+            version: VERSION,
+            notifications: self.notifications.clone(),
+        };
+        let content = serde_json::to_vec_pretty(&file)
+            .map_err(|err| NotificationError::Store(err.to_string()))?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, content)
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|err| NotificationError::Store(err.to_string()))
+    }
+
+    /// Compares the store with what the backend still has scheduled and restores the rest
+    pub(crate) fn reconcile(
+        &mut self,
+        backend: &dyn NotificationBackend,
+        catch_up: CatchUp,
+        now: SystemTime,
+    ) -> Result<Reconciliation, NotificationError> {
+        let ids = |notifications: Vec<Notification>| -> HashSet<String> {
+            notifications
+                .iter()
+                .map(|n| n.get_identifier().to_string())
+                .collect()
+        };
+        let scheduled = ids(backend.scheduled_notifications());
+        let delivered = ids(backend.delivered_notifications());
+        let mut report = Reconciliation::default();
+
+        for notification in self.notifications() {
+            let identifier = notification.get_identifier().to_string();
+            if scheduled.contains(&identifier) {
+                report.pending.push(identifier);
+                continue;
+            }
+            let delivery_date = notification.get_delivery_date().unwrap_or(now);
+            if delivery_date > now {
+                backend.schedule(&notification)?;
+                report.rescheduled.push(identifier);
+                continue;
+            }
+            if !delivered.contains(&identifier) {
+                let late = now.duration_since(delivery_date).unwrap_or_default();
+                let fire = match catch_up {
+                    CatchUp::Skip => false,
+                    CatchUp::FireOnce => true,
+                    CatchUp::FireWithin(limit) => late <= limit,
+                };
+                if fire {
+                    backend.deliver(&notification)?;
+                    report.fired.push(identifier.clone());
+                } else {
+                    report.skipped.push(identifier.clone());
+                }
+            }
+            match notification.next_occurrence(now) {
+                Some(next) => {
+                    backend.schedule(&next)?;
+                    self.insert(&next)?;
+                    report.rescheduled.push(identifier);
+                }
+                None => self.remove(&identifier)?,
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::recurrence::Recurrence;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    /// A store in a fresh file, removed again when the test is done
+    struct TempStore(ScheduleStore);
+
+    impl TempStore {
+        fn new(name: &str, notifications: &[Notification]) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "mac-notifications-store-{}-{}.json",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            let mut store = ScheduleStore::open(path).unwrap();
+            for notification in notifications {
+                store.insert(notification).unwrap();
+            }
+            Self(store)
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(self.0.path());
+        }
+    }
+
+    fn at(identifier: &str, delivery_date: SystemTime) -> Notification {
+        Notification::new()
+            .identifier(identifier)
+            .title(identifier)
+            .delivery_date(delivery_date)
+    }
+
+    fn ids(notifications: Vec<Notification>) -> Vec<String> {
+        notifications
+            .iter()
+            .map(|n| n.get_identifier().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn scheduled_by_the_backend_is_pending() {
+        let now = SystemTime::now();
+        let later = at("later", now + HOUR);
+        let mut store = TempStore::new("pending", std::slice::from_ref(&later));
+        let mock = MockBackend::new();
+        mock.schedule(&later).unwrap();
+
+        let report = store.0.reconcile(&mock, CatchUp::Skip, now).unwrap();
+        assert_eq!(report.pending, vec!["later"]);
+        assert!(report.rescheduled.is_empty());
+        assert_eq!(mock.sent().len(), 1);
+        assert!(store.0.contains("later"));
+    }
+
+    #[test]
+    fn lost_by_the_backend_is_rescheduled() {
+        let now = SystemTime::now();
+        let mut store = TempStore::new("lost", &[at("later", now + HOUR)]);
+        let mock = MockBackend::new();
+
+        let report = store.0.reconcile(&mock, CatchUp::Skip, now).unwrap();
+        assert_eq!(report.rescheduled, vec!["later"]);
+        assert_eq!(ids(mock.scheduled_notifications()), vec!["later"]);
+    }
+
+    #[test]
+    fn missed_with_skip() {
+        let now = SystemTime::now();
+        let mut store = TempStore::new("skip", &[at("missed", now - HOUR)]);
+        let mock = MockBackend::new();
+
+        let report = store.0.reconcile(&mock, CatchUp::Skip, now).unwrap();
+        assert_eq!(report.skipped, vec!["missed"]);
+        assert!(mock.sent().is_empty());
+        assert!(!store.0.contains("missed"));
+    }
+
+    #[test]
+    fn missed_with_fire_once() {
+        let now = SystemTime::now();
+        let mut store = TempStore::new("fire-once", &[at("missed", now - 100 * HOUR)]);
+        let mock = MockBackend::new();
+
+        let report = store.0.reconcile(&mock, CatchUp::FireOnce, now).unwrap();
+        assert_eq!(report.fired, vec!["missed"]);
+        assert_eq!(ids(mock.delivered_notifications()), vec!["missed"]);
+        assert!(!store.0.contains("missed"));
+    }
+
+    #[test]
+    fn missed_with_fire_within() {
+        let now = SystemTime::now();
+        let mut store = TempStore::new(
+            "fire-within",
+            &[at("recent", now - HOUR), at("old", now - 3 * HOUR)],
+        );
+        let mock = MockBackend::new();
+
+        let report = store
+            .0
+            .reconcile(&mock, CatchUp::FireWithin(2 * HOUR), now)
+            .unwrap();
+        assert_eq!(report.fired, vec!["recent"]);
+        assert_eq!(report.skipped, vec!["old"]);
+        assert_eq!(ids(mock.delivered_notifications()), vec!["recent"]);
+        assert!(store.0.notifications().is_empty());
+    }
+
+    #[test]
+    fn already_delivered_is_not_fired_again() {
+        let now = SystemTime::now();
+        let missed = at("missed", now - HOUR);
+        let mut store = TempStore::new("delivered", std::slice::from_ref(&missed));
+        let mock = MockBackend::new();
+        mock.deliver(&missed).unwrap();
+
+        let report = store.0.reconcile(&mock, CatchUp::FireOnce, now).unwrap();
+        assert_eq!(report, Reconciliation::default());
+        assert_eq!(mock.sent().len(), 1);
+        assert!(!store.0.contains("missed"));
+    }
+
+    #[test]
+    fn missed_recurring_moves_on_to_the_next_repetition() {
+        let now = SystemTime::now();
+        let daily = at("daily", now - 50 * HOUR).recurrence(Recurrence::Days(1));
+        let mut store = TempStore::new("recurring", &[daily]);
+        let mock = MockBackend::new();
+
+        let report = store.0.reconcile(&mock, CatchUp::FireOnce, now).unwrap();
+        assert_eq!(report.fired, vec!["daily"]);
+        assert_eq!(report.rescheduled, vec!["daily"]);
+        let next = store.0.notifications()[0].get_delivery_date().unwrap();
+        assert_eq!(next, now + 22 * HOUR);
+        assert_eq!(
+            mock.scheduled_notifications()[0].get_delivery_date(),
+            Some(next)
+        );
+    }
+
+    #[test]
+    fn reconciled_store_is_written() {
+        let now = SystemTime::now();
+        let mut store = TempStore::new("written", &[at("missed", now - HOUR)]);
+        store
+            .0
+            .reconcile(&MockBackend::new(), CatchUp::Skip, now)
+            .unwrap();
+        let reopened = ScheduleStore::open(store.0.path()).unwrap();
+        assert!(reopened.notifications().is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let error = parse(br#"{ "version": 2, "notifications": [] }"#).unwrap_err();
+        assert!(error.to_string().contains("unsupported version 2"));
+    }
+}
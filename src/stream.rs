@@ -0,0 +1,212 @@
+//! # Async API
+//! Available with the `async` feature. The stream and the future drive the backend themselves:
+//! every poll without a pending event runs the main loop once, so they have to be polled on the main thread
+//! and there is no need to call `run_main_loop_once` while awaiting them.
+//!
+//! With the NSUserNotification and D-Bus backends a poll blocks the thread for up to .1 seconds
+//! and the task is woken again right away, so use `futures::executor::block_on` or a local executor
+//! which runs nothing else on the main thread. Backends without a main loop, like `MockBackend`,
+//! wake the task only when an event was dispatched.
+
+use crate::provider::Context;
+use crate::{Notification, NotificationError, NotificationEvent, NotificationResponse};
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Stream of the identifiers and responses of all notifications, created by `NotificationProvider::responses`.
+/// The stream never ends, it keeps the backend of the provider alive
+pub struct ResponseStream {
+    context: Rc<Context>,
+    receiver: Receiver<NotificationEvent>,
+}
+
+impl ResponseStream {
+    pub(crate) fn new(context: Rc<Context>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        context.dispatcher.subscribe(sender);
+        Self { context, receiver }
+    }
+
+    /// The sender belongs to the dispatcher in `context`, so the channel is never disconnected
+    fn try_next(&self) -> Option<(String, NotificationResponse)> {
+        self.receiver.try_iter().find_map(|event| {
+            let response = event.response()?;
+            Some((event.identifier().to_string(), response))
+        })
+    }
+
+    fn poll_response(&self, cx: &mut TaskContext<'_>) -> Poll<(String, NotificationResponse)> {
+        if let Some(item) = self.try_next() {
+            return Poll::Ready(item);
+        }
+        self.context.backend.run_main_loop_once();
+        if let Some(item) = self.try_next() {
+            return Poll::Ready(item);
+        }
+        self.context.dispatcher.register_waker(cx.waker());
+        if self.context.backend.has_main_loop() {
+            // events of the system only arrive while the main loop runs again
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = (String, NotificationResponse);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_response(cx).map(Some)
+    }
+}
+
+enum State {
+    Failed(Option<NotificationError>),
+    Waiting {
+        responses: ResponseStream,
+        identifier: String,
+        deadline: Instant,
+        /// wakes the task at the deadline, started with the first `Poll::Pending`
+        timer: Option<Arc<Mutex<Waker>>>,
+    },
+}
+
+/// Future returned by `Notification::send_and_wait`
+pub struct ResponseFuture {
+    state: State,
+}
+
+impl Future for ResponseFuture {
+    type Output = Result<NotificationResponse, NotificationError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        match &mut self.get_mut().state {
+            State::Failed(error) => {
+                Poll::Ready(Err(error.take().expect("future polled after completion")))
+            }
+            State::Waiting {
+                responses,
+                identifier,
+                deadline,
+                timer,
+            } => loop {
+                match responses.poll_response(cx) {
+                    Poll::Ready((id, response)) if id == *identifier => {
+                        return Poll::Ready(Ok(response))
+                    }
+                    Poll::Ready(_) => continue,
+                    Poll::Pending if Instant::now() >= *deadline => {
+                        return Poll::Ready(Err(NotificationError::Timeout))
+                    }
+                    Poll::Pending => {
+                        match timer {
+                            Some(waker) => *waker.lock().unwrap() = cx.waker().clone(),
+                            None => *timer = Some(start_timer(*deadline, cx.waker().clone())),
+                        }
+                        return Poll::Pending;
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Wakes the task once the deadline passed, the waker can be replaced until then
+fn start_timer(deadline: Instant, waker: Waker) -> Arc<Mutex<Waker>> {
+    let waker = Arc::new(Mutex::new(waker));
+    let timer = waker.clone();
+    thread::spawn(move || {
+        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        timer.lock().unwrap().wake_by_ref();
+    });
+    waker
+}
+
+impl Notification {
+    /// Sends the notification and returns a future which resolves with the response of the user
+    /// to exactly this notification, or with `NotificationError::Timeout` once the timeout elapsed.
+    /// The notification is sent immediately, not when the future is polled for the first time.
+    ///
+    /// Fails with `NotificationError::NoProvider` without a `NotificationProvider` on the current thread
+    /// # Example
+    /// ```rust
+    /// use futures::executor::block_on;
+    /// use mac_notifications::backend::MockBackend;
+    /// use mac_notifications::{Notification, NotificationProvider, NotificationResponse};
+    /// use std::time::Duration;
+    ///
+    /// let mock = MockBackend::new();
+    /// let _provider = NotificationProvider::with_backend(mock.clone());
+    ///
+    /// let notification = Notification::new().title("Deploy to production?").reply(true);
+    /// let id = notification.get_identifier().to_string();
+    /// let response = notification.send_and_wait(Duration::from_secs(60));
+    ///
+    /// mock.click("some other notification");
+    /// mock.reply(&id, "yes");
+    /// assert_eq!(
+    ///     block_on(response).unwrap(),
+    ///     NotificationResponse::Reply("yes".to_string())
+    /// );
+    /// ```
+    pub fn send_and_wait(self, timeout: Duration) -> ResponseFuture {
+        let context = match crate::provider::current() {
+            Some(context) => context,
+            None => {
+                return ResponseFuture {
+                    state: State::Failed(Some(NotificationError::NoProvider)),
+                }
+            }
+        };
+        // subscribe first, the backend may report a response while sending
+        let responses = ResponseStream::new(context);
+        let state = match self.send() {
+            Ok(identifier) => State::Waiting {
+                responses,
+                identifier,
+                deadline: Instant::now() + timeout,
+                timer: None,
+            },
+            Err(error) => State::Failed(Some(error)),
+        };
+        ResponseFuture { state }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::MockBackend;
+    use crate::{Notification, NotificationError, NotificationProvider};
+    use futures::executor::block_on;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn times_out_without_response() {
+        let _provider = NotificationProvider::with_backend(MockBackend::new());
+        let start = Instant::now();
+        let response = Notification::new()
+            .title("Anyone?")
+            .send_and_wait(Duration::from_millis(200));
+        assert!(matches!(
+            block_on(response),
+            Err(NotificationError::Timeout)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn fails_without_provider() {
+        let response = Notification::new().send_and_wait(Duration::from_secs(60));
+        assert!(matches!(
+            block_on(response),
+            Err(NotificationError::NoProvider)
+        ));
+    }
+}
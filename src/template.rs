@@ -0,0 +1,395 @@
+use crate::{Notification, NotificationAction};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hash};
+
+/// Why a `NotificationTemplate` could not be rendered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// The template uses a variable which was not given
+    MissingVariable(String),
+    /// A `{` is not closed, a `}` is not opened or a placeholder is empty.
+    /// `position` is the byte offset in the template
+    Syntax { template: String, position: usize },
+    /// The value is no struct or map and has therefore no variables
+    InvalidValue(String),
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::MissingVariable(name) => write!(f, "missing variable: {}", name),
+            TemplateError::Syntax { template, position } => {
+                write!(f, "invalid placeholder at {} in \"{}\"", position, template)
+            }
+            TemplateError::InvalidValue(message) => write!(f, "invalid value: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Values a `NotificationTemplate` is rendered with
+pub trait Variables {
+    /// The value of the variable, `None` if there is no such variable
+    fn variable(&self, name: &str) -> Option<String>;
+}
+
+impl<K, V, S> Variables for HashMap<K, V, S>
+where
+    K: Borrow<str> + Hash + Eq,
+    V: Display,
+    S: BuildHasher,
+{
+    fn variable(&self, name: &str) -> Option<String> {
+        self.get(name).map(|value| value.to_string())
+    }
+}
+
+impl<K, V> Variables for BTreeMap<K, V>
+where
+    K: Borrow<str> + Ord,
+    V: Display,
+{
+    fn variable(&self, name: &str) -> Option<String> {
+        self.get(name).map(|value| value.to_string())
+    }
+}
+
+/// Nested fields are separated by dots, e.g. `{user.name}`. Strings are inserted without quotes
+#[cfg(feature = "serde")]
+impl Variables for serde_json::Value {
+    fn variable(&self, name: &str) -> Option<String> {
+        let value = name.split('.').try_fold(self, |value, field| match value {
+            serde_json::Value::Array(items) => items.get(field.parse::<usize>().ok()?),
+            _ => value.get(field),
+        })?;
+        match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(text) => Some(text.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+/// Replaces the `{placeholders}` of the template, `{{` and `}}` stand for literal braces
+fn render(template: &str, variables: &dyn Variables) -> Result<String, TemplateError> {
+    let syntax = |rest: &str| TemplateError::Syntax {
+        template: template.to_string(),
+        position: template.len() - rest.len(),
+    };
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(tail) = rest.strip_prefix("{{") {
+            rendered.push('{');
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("}}") {
+            rendered.push('}');
+            rest = tail;
+        } else if rest.starts_with('}') {
+            return Err(syntax(rest));
+        } else {
+            let end = rest.find('}').ok_or_else(|| syntax(rest))?;
+            let name = rest[1..end].trim();
+            if name.is_empty() || name.contains('{') {
+                return Err(syntax(rest));
+            }
+            let value = variables
+                .variable(name)
+                .ok_or_else(|| TemplateError::MissingVariable(name.to_string()))?;
+            rendered.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// A notification with `{placeholders}` in its texts, rendered into a `Notification` for every use
+/// # Example
+/// ```rust
+/// use mac_notifications::{NotificationTemplate, TemplateError};
+/// use std::collections::HashMap;
+///
+/// let template = NotificationTemplate::new()
+///     .title("{service} is down")
+///     .body("{count} checks failed on {host}")
+///     .action("restart", "Restart {service}");
+///
+/// let variables = HashMap::from([("service", "nginx"), ("host", "web-1"), ("count", "3")]);
+/// let notification = template.render(&variables).unwrap();
+/// assert_eq!(notification.get_title(), Some("nginx is down"));
+/// assert_eq!(notification.get_body(), Some("3 checks failed on web-1"));
+/// assert_eq!(notification.get_action().unwrap().title, "Restart nginx");
+///
+/// let variables = HashMap::from([("service", "nginx")]);
+/// assert_eq!(
+///     template.render(&variables).unwrap_err(),
+///     TemplateError::MissingVariable("count".to_string())
+/// );
+/// ```
+/// With the `serde` feature any serializable value can be used, e.g. a struct
+/// ```rust
+/// # #[cfg(feature = "serde")] {
+/// use mac_notifications::NotificationTemplate;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Build {
+///     project: String,
+///     number: u32,
+///     commit: Commit,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Commit {
+///     author: String,
+/// }
+///
+/// let template = NotificationTemplate::new()
+///     .title("{project} #{number} failed")
+///     .subtitle("last commit by {commit.author}");
+/// let build = Build {
+///     project: "web".to_string(),
+///     number: 42,
+///     commit: Commit {
+///         author: "kim".to_string(),
+///     },
+/// };
+/// let notification = template.render_value(&build).unwrap();
+/// assert_eq!(notification.get_title(), Some("web #42 failed"));
+/// assert_eq!(notification.get_subtitle(), Some("last commit by kim"));
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NotificationTemplate {
+    title: Option<String>,
+    subtitle: Option<String>,
+    body: Option<String>,
+    sound: Option<String>,
+    image: Option<String>,
+    reply: bool,
+    /// The titles are templates, the identifiers are not
+    action: Option<NotificationAction>,
+    alternate_actions: Vec<NotificationAction>,
+    close_button: Option<String>,
+}
+
+impl NotificationTemplate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn subtitle(mut self, subtitle: &str) -> Self {
+        self.subtitle = Some(subtitle.to_string());
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self
+    }
+
+    pub fn sound(mut self, sound: &str) -> Self {
+        self.sound = Some(sound.to_string());
+        self
+    }
+
+    /// A file path or URL, see `Notification::image`
+    pub fn image(mut self, image: &str) -> Self {
+        self.image = Some(image.to_string());
+        self
+    }
+
+    pub fn reply(mut self, reply: bool) -> Self {
+        self.reply = reply;
+        self
+    }
+
+    /// See `Notification::action`, only the title is a template
+    pub fn action(mut self, identifier: &str, title: &str) -> Self {
+        self.action = Some(NotificationAction::new(identifier, title));
+        self
+    }
+
+    /// See `Notification::alternate_action`, only the title is a template
+    pub fn alternate_action(mut self, identifier: &str, title: &str) -> Self {
+        self.alternate_actions
+            .push(NotificationAction::new(identifier, title));
+        self
+    }
+
+    pub fn close_button(mut self, title: &str) -> Self {
+        self.close_button = Some(title.to_string());
+        self
+    }
+
+    /// Creates a notification with a new identifier and the placeholders replaced by the variables
+    pub fn render(&self, variables: &impl Variables) -> Result<Notification, TemplateError> {
+        let text = |template: &Option<String>| {
+            template
+                .as_deref()
+                .map(|template| render(template, variables))
+                .transpose()
+        };
+        let mut notification = Notification::new().reply(self.reply);
+        if let Some(title) = text(&self.title)? {
+            notification = notification.title(&title);
+        }
+        if let Some(subtitle) = text(&self.subtitle)? {
+            notification = notification.subtitle(&subtitle);
+        }
+        if let Some(body) = text(&self.body)? {
+            notification = notification.body(&body);
+        }
+        if let Some(sound) = text(&self.sound)? {
+            notification = notification.sound(&sound);
+        }
+        if let Some(image) = text(&self.image)? {
+            notification = notification.image(&image);
+        }
+        if let Some(action) = self.action.as_ref() {
+            notification =
+                notification.action(&action.identifier, &render(&action.title, variables)?);
+        }
+        for action in self.alternate_actions.iter() {
+            notification = notification
+                .alternate_action(&action.identifier, &render(&action.title, variables)?);
+        }
+        if let Some(close_button) = text(&self.close_button)? {
+            notification = notification.close_button(&close_button);
+        }
+        Ok(notification)
+    }
+
+    /// Like `render`, with the fields of a struct or the entries of a map as variables
+    #[cfg(feature = "serde")]
+    pub fn render_value<T: serde::Serialize>(
+        &self,
+        value: &T,
+    ) -> Result<Notification, TemplateError> {
+        let value = serde_json::to_value(value)
+            .map_err(|err| TemplateError::InvalidValue(err.to_string()))?;
+        if !value.is_object() {
+            return Err(TemplateError::InvalidValue(
+                "expected a struct or map".to_string(),
+            ));
+        }
+        self.render(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> HashMap<&'static str, &'static str> {
+        HashMap::from([("name", "nginx"), ("count", "3")])
+    }
+
+    fn syntax(template: &str, position: usize) -> Result<String, TemplateError> {
+        Err(TemplateError::Syntax {
+            template: template.to_string(),
+            position,
+        })
+    }
+
+    #[test]
+    fn placeholders_and_escapes() {
+        let render = |template| render(template, &variables());
+        assert_eq!(render("{name}: {count}"), Ok("nginx: 3".to_string()));
+        assert_eq!(render("{ name }"), Ok("nginx".to_string()));
+        assert_eq!(render("{{name}}"), Ok("{name}".to_string()));
+        assert_eq!(render("{{{name}}}"), Ok("{nginx}".to_string()));
+        assert_eq!(render("no placeholders"), Ok("no placeholders".to_string()));
+        assert_eq!(render(""), Ok(String::new()));
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_placeholder() {
+        for (template, position) in [
+            ("Hello {name", 6),
+            ("a } b", 2),
+            ("{}", 0),
+            ("{  } left", 0),
+            ("{a{name}", 0),
+            ("}}}", 2),
+            ("{name}{", 6),
+            // the position is a byte offset
+            ("Grüße {", 8),
+        ] {
+            assert_eq!(
+                render(template, &variables()),
+                syntax(template, position),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn missing_variables() {
+        assert_eq!(
+            render("{name} on {host}", &variables()),
+            Err(TemplateError::MissingVariable("host".to_string()))
+        );
+        // checked in order, the syntax error comes after the missing variable
+        assert_eq!(
+            render("{host} {", &variables()),
+            Err(TemplateError::MissingVariable("host".to_string()))
+        );
+    }
+
+    #[test]
+    fn every_text_is_rendered() {
+        let template = NotificationTemplate::new()
+            .title("{name}")
+            .action("restart", "Restart {name");
+        assert_eq!(
+            template.render(&variables()).unwrap_err(),
+            TemplateError::Syntax {
+                template: "Restart {name".to_string(),
+                position: 8,
+            }
+        );
+        let template = NotificationTemplate::new().close_button("Ignore {count}");
+        let notification = template.render(&variables()).unwrap();
+        assert_eq!(notification.get_close_button(), Some("Ignore 3"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_values() {
+        let value = serde_json::json!({
+            "service": { "name": "nginx", "port": 443 },
+            "hosts": ["web-1", "web-2"],
+            "owner": null,
+        });
+        assert_eq!(
+            render("{service.name}:{service.port} on {hosts.1}", &value),
+            Ok("nginx:443 on web-2".to_string())
+        );
+        for name in ["owner", "service.missing", "hosts.2", "hosts.first"] {
+            assert_eq!(
+                render(&format!("{{{}}}", name), &value),
+                Err(TemplateError::MissingVariable(name.to_string()))
+            );
+        }
+        let template = NotificationTemplate::new().title("{name}");
+        assert!(matches!(
+            template.render_value(&["nginx"]),
+            Err(TemplateError::InvalidValue(_))
+        ));
+    }
+}
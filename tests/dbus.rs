@@ -0,0 +1,369 @@
+//! Runs `DbusBackend` against a stub notification server on a private `dbus-daemon --session`.
+//! Skipped if `dbus-daemon` is not installed
+#![cfg(feature = "dbus")]
+
+use mac_notifications::backend::DbusBackend;
+use mac_notifications::{Notification, NotificationProvider, NotificationResponse};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedValue;
+
+const PATH: &str = "/org/freedesktop/Notifications";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Notify {
+    app_name: String,
+    replaces_id: u32,
+    summary: String,
+    body: String,
+    actions: Vec<String>,
+    hints: Vec<String>,
+}
+
+#[derive(Default)]
+struct Server {
+    last_id: u32,
+    /// `Notify` fails while it is set
+    failing: Arc<AtomicBool>,
+    notified: Arc<Mutex<Vec<Notify>>>,
+    closed: Arc<Mutex<Vec<u32>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl Server {
+    fn get_capabilities(&self) -> Vec<String> {
+        vec!["actions".into(), "body".into(), "inline-reply".into()]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        app_name: String,
+        replaces_id: u32,
+        _app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> zbus::fdo::Result<u32> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(zbus::fdo::Error::Failed("not now".to_string()));
+        }
+        let mut hints: Vec<String> = hints.into_keys().collect();
+        hints.sort();
+        self.notified.lock().unwrap().push(Notify {
+            app_name,
+            replaces_id,
+            summary,
+            body,
+            actions,
+            hints,
+        });
+        if replaces_id != 0 {
+            return Ok(replaces_id);
+        }
+        self.last_id += 1;
+        Ok(self.last_id)
+    }
+
+    fn close_notification(&self, id: u32) {
+        self.closed.lock().unwrap().push(id);
+    }
+
+    #[zbus(signal)]
+    async fn notification_closed(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn action_invoked(emitter: &SignalEmitter<'_>, id: u32, key: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn notification_replied(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        text: &str,
+    ) -> zbus::Result<()>;
+}
+
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+
+    fn connect(&self) -> Builder<'_> {
+        Builder::address(self.address.as_str()).unwrap()
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+struct Harness {
+    server: Connection,
+    notified: Arc<Mutex<Vec<Notify>>>,
+    closed: Arc<Mutex<Vec<u32>>>,
+    failing: Arc<AtomicBool>,
+    provider: NotificationProvider,
+    responses: Rc<RefCell<Vec<(String, NotificationResponse)>>>,
+    // dropped last, the bus has to outlive both connections
+    _bus: Bus,
+}
+
+impl Harness {
+    fn start() -> Option<Self> {
+        let Some(bus) = Bus::start() else {
+            eprintln!("dbus-daemon is not installed, skipping");
+            return None;
+        };
+        let stub = Server::default();
+        let notified = stub.notified.clone();
+        let closed = stub.closed.clone();
+        let failing = stub.failing.clone();
+        let server = bus
+            .connect()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at(PATH, stub)
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = bus.connect().build().unwrap();
+        let backend = DbusBackend::with_connection(client, "Test").unwrap();
+        let mut provider = NotificationProvider::with_backend(backend);
+        let responses = Rc::new(RefCell::new(vec![]));
+        let recorded = responses.clone();
+        provider.set_callback(move |id, response| recorded.borrow_mut().push((id, response)));
+        Some(Self {
+            server,
+            notified,
+            closed,
+            failing,
+            provider,
+            responses,
+            _bus: bus,
+        })
+    }
+
+    fn emitter(&self) -> SignalEmitter<'static> {
+        let interface = self
+            .server
+            .object_server()
+            .interface::<_, Server>(PATH)
+            .unwrap();
+        interface.signal_emitter().clone()
+    }
+
+    /// Runs the main loop until `count` responses arrived
+    fn wait_for_responses(&self, count: usize) -> Vec<(String, NotificationResponse)> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while self.responses.borrow().len() < count && Instant::now() < deadline {
+            self.provider.run_main_loop_once();
+        }
+        self.responses.borrow().clone()
+    }
+}
+
+#[test]
+fn notify_maps_the_notification() {
+    let Some(harness) = Harness::start() else {
+        return;
+    };
+    Notification::new()
+        .title("Build failed")
+        .subtitle("main")
+        .body("3 tests failed")
+        .sound("bell")
+        .action("retry", "Retry")
+        .alternate_action("logs", "Show logs")
+        .close_button("Ignore")
+        .reply(true)
+        .send()
+        .unwrap();
+
+    let notified = harness.notified.lock().unwrap().clone();
+    assert_eq!(
+        notified,
+        vec![Notify {
+            app_name: "Test".to_string(),
+            replaces_id: 0,
+            summary: "Build failed".to_string(),
+            body: "main\n3 tests failed".to_string(),
+            actions: [
+                "default",
+                "",
+                "retry",
+                "Retry",
+                "logs",
+                "Show logs",
+                "mac-notifications-close",
+                "Ignore",
+                "inline-reply",
+                "Reply",
+            ]
+            .map(String::from)
+            .to_vec(),
+            hints: vec!["sound-name".to_string()],
+        }]
+    );
+    assert_eq!(harness.provider.get_all_notifications().len(), 1);
+}
+
+#[test]
+fn sending_the_same_identifier_replaces_the_notification() {
+    let Some(harness) = Harness::start() else {
+        return;
+    };
+    let first = Notification::new().title("Progress 10%").send().unwrap();
+    Notification::new()
+        .identifier(&first)
+        .title("Progress 90%")
+        .send()
+        .unwrap();
+    let notified = harness.notified.lock().unwrap().clone();
+    assert_eq!(notified[1].replaces_id, 1);
+    assert_eq!(harness.provider.get_all_notifications().len(), 1);
+}
+
+#[test]
+fn signals_become_responses() {
+    let Some(harness) = Harness::start() else {
+        return;
+    };
+    let clicked = Notification::new().title("One").send().unwrap();
+    let replied = Notification::new().title("Two").reply(true).send().unwrap();
+    let pressed = Notification::new()
+        .title("Three")
+        .action("retry", "Retry")
+        .close_button("Ignore")
+        .send()
+        .unwrap();
+    let dismissed = Notification::new().title("Four").send().unwrap();
+    let expired = Notification::new().title("Five").send().unwrap();
+
+    let emitter = harness.emitter();
+    zbus::block_on(async {
+        Server::action_invoked(&emitter, 1, "default").await?;
+        Server::action_invoked(&emitter, 2, "inline-reply").await?;
+        Server::notification_replied(&emitter, 2, "ok").await?;
+        Server::action_invoked(&emitter, 3, "retry").await?;
+        // closed after an interaction, not reported again
+        Server::notification_closed(&emitter, 3, 2).await?;
+        Server::notification_closed(&emitter, 4, 2).await?;
+        Server::notification_closed(&emitter, 5, 1).await?;
+        // unknown to the backend
+        Server::action_invoked(&emitter, 99, "default").await
+    })
+    .unwrap();
+
+    assert_eq!(
+        harness.wait_for_responses(5),
+        vec![
+            (clicked, NotificationResponse::Click),
+            (replied, NotificationResponse::Reply("ok".to_string())),
+            (
+                pressed,
+                NotificationResponse::ActionButton("retry".to_string())
+            ),
+            (dismissed, NotificationResponse::Dismissed),
+            (expired, NotificationResponse::None),
+        ]
+    );
+    // closed notifications are not delivered anymore
+    assert_eq!(harness.provider.get_all_notifications().len(), 2);
+}
+
+#[test]
+fn custom_close_button_is_reported_with_its_title() {
+    let Some(harness) = Harness::start() else {
+        return;
+    };
+    let id = Notification::new()
+        .title("Disk almost full")
+        .close_button("Ignore")
+        .send()
+        .unwrap();
+    let emitter = harness.emitter();
+    zbus::block_on(Server::action_invoked(
+        &emitter,
+        1,
+        "mac-notifications-close",
+    ))
+    .unwrap();
+    assert_eq!(
+        harness.wait_for_responses(1),
+        vec![(id, NotificationResponse::CloseButton("Ignore".to_string()))]
+    );
+}
+
+#[test]
+fn delete_closes_the_notification() {
+    let Some(harness) = Harness::start() else {
+        return;
+    };
+    let id = Notification::new().title("Hello").send().unwrap();
+    Notification::new().title("World").send().unwrap();
+    harness.provider.delete(&id);
+    assert_eq!(*harness.closed.lock().unwrap(), vec![1]);
+    harness.provider.delete_all();
+    assert_eq!(*harness.closed.lock().unwrap(), vec![1, 2]);
+    assert!(harness.provider.get_all_notifications().is_empty());
+}
+
+#[test]
+fn failed_scheduled_deliveries_are_retried() {
+    let Some(harness) = Harness::start() else {
+        return;
+    };
+    harness.failing.store(true, Ordering::SeqCst);
+    let due = SystemTime::now() - Duration::from_secs(1);
+    let id = Notification::new()
+        .title("Standup")
+        .delivery_date(due)
+        .send()
+        .unwrap();
+    harness.provider.run_main_loop_once();
+    assert_eq!(harness.provider.get_scheduled_notifications().len(), 1);
+    assert!(harness.provider.get_all_notifications().is_empty());
+
+    harness.failing.store(false, Ordering::SeqCst);
+    harness.provider.run_main_loop_once();
+    assert!(harness.provider.get_scheduled_notifications().is_empty());
+    let delivered = harness.provider.get_all_notifications();
+    assert_eq!(delivered[0].get_identifier(), id);
+    assert_eq!(harness.notified.lock().unwrap()[0].summary, "Standup");
+}